@@ -0,0 +1,176 @@
+//! Compares sending a small frame header followed by its body as two writes
+//! against a single vectored write, counting the TLS records and transport
+//! writes each approach needs.
+//!
+//! Run with `cargo bench --bench vectored`.
+
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use futures::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, IoSlice};
+use futures::{FutureExt, StreamExt};
+use futures_tokio_compat::Compat;
+use tls_async::{Identity, TlsAcceptor, TlsConnector};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::runtime::Runtime;
+
+const FRAMES: usize = 10_000;
+const HEADER: [u8; 9] = [0u8; 9];
+const BODY: [u8; 1024] = [0u8; 1024];
+
+/// Counts writes made to the transport and the TLS records they carry.
+struct Counting<S> {
+    inner: S,
+    writes: usize,
+    records: usize,
+    header: Vec<u8>,
+    remaining: usize,
+}
+
+impl<S> Counting<S> {
+    fn new(inner: S) -> Counting<S> {
+        Counting {
+            inner,
+            writes: 0,
+            records: 0,
+            header: Vec::with_capacity(5),
+            remaining: 0,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.writes = 0;
+        self.records = 0;
+    }
+
+    fn count_records(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            if self.remaining == 0 {
+                let take = std::cmp::min(5 - self.header.len(), data.len());
+                self.header.extend_from_slice(&data[..take]);
+                data = &data[take..];
+                if self.header.len() == 5 {
+                    self.records += 1;
+                    self.remaining = u16::from_be_bytes([self.header[3], self.header[4]]) as usize;
+                    self.header.clear();
+                }
+            } else {
+                let take = std::cmp::min(self.remaining, data.len());
+                self.remaining -= take;
+                data = &data[take..];
+            }
+        }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for Counting<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for Counting<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let r = Pin::new(&mut self.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(n)) = r {
+            self.writes += 1;
+            self.count_records(&buf[..n]);
+        }
+        r
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_close(cx)
+    }
+}
+
+struct Stats {
+    records: usize,
+    writes: usize,
+    elapsed: Duration,
+}
+
+async fn run(vectored: bool) -> Stats {
+    let srv = TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("Failed to bind");
+    let addr = srv.local_addr().expect("No local address");
+
+    let der = include_bytes!("../examples/identity.p12");
+    let identity = Identity::from_pkcs12(der, "mypass").expect("Failed to create identity");
+    let acceptor = TlsAcceptor::new(identity).expect("Failed to build acceptor");
+    let connector = TlsConnector::builder()
+        .danger_accept_invalid_certs(true)
+        .build()
+        .expect("Failed to build connector");
+
+    let server = async move {
+        let mut incoming = srv.incoming();
+        let socket = Compat::new(incoming.next().await.unwrap().expect("Failed to accept"));
+        let mut stream = acceptor.accept(socket).await.expect("Failed to accept tls");
+        let mut sink = vec![];
+        stream.read_to_end(&mut sink).await.expect("Failed to read");
+    };
+
+    let client = async move {
+        let socket = TcpStream::connect(&addr).await.expect("Failed to connect");
+        let socket = Counting::new(Compat::new(socket));
+        let mut stream = connector
+            .connect("foobar.com", socket)
+            .await
+            .expect("Failed to connect tls");
+        stream.get_mut().reset();
+
+        let start = Instant::now();
+        for _ in 0..FRAMES {
+            if vectored {
+                let bufs = [IoSlice::new(&HEADER), IoSlice::new(&BODY)];
+                let n = stream.write_vectored(&bufs).await.expect("Failed to write");
+                let rest: Vec<u8> = HEADER.iter().chain(BODY.iter()).skip(n).cloned().collect();
+                stream.write_all(&rest).await.expect("Failed to write");
+            } else {
+                stream.write_all(&HEADER).await.expect("Failed to write");
+                stream.write_all(&BODY).await.expect("Failed to write");
+            }
+        }
+        stream.flush().await.expect("Failed to flush");
+        let elapsed = start.elapsed();
+
+        let stats = Stats {
+            records: stream.get_ref().records,
+            writes: stream.get_ref().writes,
+            elapsed,
+        };
+        stream.close().await.expect("Failed to close");
+        stats
+    };
+
+    let ((), stats) = futures::future::join(server, client).await;
+    stats
+}
+
+fn main() {
+    let rt = Runtime::new().expect("Failed to build runtime");
+
+    for &(name, vectored) in &[("separate writes", false), ("vectored write", true)] {
+        let stats = rt.block_on(run(vectored).boxed());
+        println!(
+            "{:>16}: {} frames, {} records, {} transport writes, {:?}",
+            name, FRAMES, stats.records, stats.writes, stats.elapsed
+        );
+    }
+}
@@ -0,0 +1,255 @@
+//! Reading a client's ClientHello before choosing how to accept it.
+//!
+//! `StartHandshake::read` reads the first handshake message from a transport
+//! and parses the parts useful for routing: SNI, ALPN, versions and cipher
+//! suites. The caller then finishes the handshake with whichever
+//! `TlsAcceptor` suits the client, or drops the connection. The bytes read
+//! so far are replayed to the acceptor through `Rewind`.
+
+use std::convert::TryInto;
+use std::fmt;
+
+use futures::io::{AsyncRead, AsyncWrite};
+
+use crate::rewind::fill;
+use crate::{AcceptError, Error, InvalidClientHello, Rewind, TlsAcceptor, TlsStream};
+
+/// The TLS record content type of handshake messages.
+const HANDSHAKE: u8 = 22;
+
+/// The handshake message type of a ClientHello.
+const CLIENT_HELLO: u8 = 1;
+
+/// The largest plaintext record a client may send.
+const MAX_RECORD_LEN: usize = 16 * 1024;
+
+/// The largest ClientHello accepted. Real ones, even with post-quantum key
+/// shares, are a few kilobytes.
+const MAX_CLIENT_HELLO_LEN: usize = 64 * 1024;
+
+const EXTENSION_SERVER_NAME: u16 = 0;
+const EXTENSION_ALPN: u16 = 16;
+const EXTENSION_SUPPORTED_VERSIONS: u16 = 43;
+
+/// The routing-relevant parts of a ClientHello.
+///
+/// GREASE values (RFC 8701) are left out of the versions and cipher suites.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ClientHello {
+    server_name: Option<String>,
+    alpn_protocols: Vec<Vec<u8>>,
+    versions: Vec<u16>,
+    cipher_suites: Vec<u16>,
+}
+
+impl ClientHello {
+    /// Returns the host name the client sent as SNI, if any.
+    pub fn server_name(&self) -> Option<&str> {
+        self.server_name.as_deref()
+    }
+
+    /// Returns the application protocols the client offered with ALPN, in
+    /// its order of preference.
+    pub fn alpn_protocols(&self) -> &[Vec<u8>] {
+        &self.alpn_protocols
+    }
+
+    /// Returns the protocol versions the client offered, as wire values such
+    /// as `0x0304` for TLS 1.3.
+    ///
+    /// Taken from the `supported_versions` extension, or the ClientHello's
+    /// own version if the client did not send it.
+    pub fn versions(&self) -> &[u16] {
+        &self.versions
+    }
+
+    /// Returns the cipher suites the client offered, as IANA wire values, in
+    /// its order of preference.
+    pub fn cipher_suites(&self) -> &[u16] {
+        &self.cipher_suites
+    }
+}
+
+/// A connection whose ClientHello has been read but whose handshake has not
+/// started.
+pub struct StartHandshake<S> {
+    hello: ClientHello,
+    stream: Rewind<S>,
+}
+
+impl<S> StartHandshake<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    /// Reads and parses the ClientHello from `stream`.
+    ///
+    /// Anything that is not a TLS handshake starting with a ClientHello fails
+    /// with `AcceptError::InvalidClientHello`. As with `accept`, a client
+    /// that never finishes sending one is not timed out here.
+    pub async fn read(mut stream: S) -> Result<StartHandshake<S>, AcceptError> {
+        let mut buf = Vec::new();
+        let mut message = Vec::new();
+        let mut pos = 0;
+        loop {
+            fill(&mut stream, &mut buf, pos + 5).await?;
+            if buf[pos] != HANDSHAKE {
+                return Err(invalid("not a TLS handshake record"));
+            }
+            let len = usize::from(u16::from_be_bytes([buf[pos + 3], buf[pos + 4]]));
+            if len == 0 || len > MAX_RECORD_LEN {
+                return Err(invalid("bad record length"));
+            }
+            fill(&mut stream, &mut buf, pos + 5 + len).await?;
+            message.extend_from_slice(&buf[pos + 5..pos + 5 + len]);
+            pos += 5 + len;
+
+            if message.len() >= 4 {
+                if message[0] != CLIENT_HELLO {
+                    return Err(invalid("first handshake message is not a ClientHello"));
+                }
+                let len = u32::from_be_bytes([0, message[1], message[2], message[3]]) as usize;
+                if len > MAX_CLIENT_HELLO_LEN {
+                    return Err(invalid("ClientHello is too long"));
+                }
+                if message.len() >= 4 + len {
+                    let hello = parse(&message[4..4 + len]).ok_or_else(|| invalid("malformed"))?;
+                    return Ok(StartHandshake {
+                        hello,
+                        stream: Rewind::new(buf, stream),
+                    });
+                }
+            }
+        }
+    }
+
+    /// Returns the parsed ClientHello.
+    pub fn client_hello(&self) -> &ClientHello {
+        &self.hello
+    }
+
+    /// Completes the handshake with `acceptor`.
+    pub async fn accept(self, acceptor: &TlsAcceptor) -> Result<TlsStream<Rewind<S>>, Error> {
+        acceptor.accept(self.stream).await
+    }
+
+    /// Returns the transport without completing the handshake, for example to
+    /// pass the connection through to a backend. Reading from it yields the
+    /// ClientHello first.
+    pub fn into_inner(self) -> Rewind<S> {
+        self.stream
+    }
+}
+
+impl<S> fmt::Debug for StartHandshake<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StartHandshake")
+            .field("client_hello", &self.hello)
+            .finish()
+    }
+}
+
+fn invalid(reason: &'static str) -> AcceptError {
+    AcceptError::InvalidClientHello(InvalidClientHello { reason })
+}
+
+fn is_grease(value: u16) -> bool {
+    value & 0x0f0f == 0x0a0a && value >> 8 == value & 0xff
+}
+
+/// A cursor over the body of a handshake message.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.0.len() < len {
+            return None;
+        }
+        let (bytes, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Some(bytes)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.bytes(1).map(|b| b[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.bytes(2)
+            .map(|b| u16::from_be_bytes(b.try_into().unwrap()))
+    }
+
+    /// Reads a vector with a one-byte length prefix.
+    fn vec8(&mut self) -> Option<Reader<'a>> {
+        let len = self.u8()?;
+        self.bytes(usize::from(len)).map(Reader)
+    }
+
+    /// Reads a vector with a two-byte length prefix.
+    fn vec16(&mut self) -> Option<Reader<'a>> {
+        let len = self.u16()?;
+        self.bytes(usize::from(len)).map(Reader)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+fn parse(body: &[u8]) -> Option<ClientHello> {
+    let mut r = Reader(body);
+    let legacy_version = r.u16()?;
+    r.bytes(32)?;
+    r.vec8()?;
+    let mut suites = r.vec16()?;
+    r.vec8()?;
+
+    let mut hello = ClientHello::default();
+    while !suites.is_empty() {
+        let suite = suites.u16()?;
+        if !is_grease(suite) {
+            hello.cipher_suites.push(suite);
+        }
+    }
+
+    let mut supported_versions = None;
+    // Extensions are absent from some pre-TLS 1.2 clients.
+    if !r.is_empty() {
+        let mut extensions = r.vec16()?;
+        while !extensions.is_empty() {
+            let kind = extensions.u16()?;
+            let mut data = extensions.vec16()?;
+            match kind {
+                EXTENSION_SERVER_NAME => {
+                    let mut names = data.vec16()?;
+                    while !names.is_empty() {
+                        let name_type = names.u8()?;
+                        let name = names.vec16()?.0;
+                        if name_type == 0 && hello.server_name.is_none() {
+                            hello.server_name = Some(std::str::from_utf8(name).ok()?.to_owned());
+                        }
+                    }
+                }
+                EXTENSION_ALPN => {
+                    let mut protocols = data.vec16()?;
+                    while !protocols.is_empty() {
+                        hello.alpn_protocols.push(protocols.vec8()?.0.to_vec());
+                    }
+                }
+                EXTENSION_SUPPORTED_VERSIONS => {
+                    let mut versions = data.vec8()?;
+                    let mut list = Vec::new();
+                    while !versions.is_empty() {
+                        let version = versions.u16()?;
+                        if !is_grease(version) {
+                            list.push(version);
+                        }
+                    }
+                    supported_versions = Some(list);
+                }
+                _ => {}
+            }
+        }
+    }
+    hello.versions = supported_versions.unwrap_or_else(|| vec![legacy_version]);
+    Some(hello)
+}
@@ -0,0 +1,109 @@
+//! Connecting by host name, racing the resolved addresses as described in
+//! RFC 8305 ("Happy Eyeballs").
+
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use async_std::net::{TcpStream, ToSocketAddrs};
+use async_std::task;
+use futures::future::{self, Either};
+use futures::stream::{FuturesUnordered, StreamExt};
+
+use crate::{ConnectError, ConnectHostError, TlsConnector, TlsStream};
+
+/// How long to wait for an attempt before starting the next one in parallel.
+/// This is the "Connection Attempt Delay" recommended by RFC 8305.
+const ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+impl TlsConnector {
+    /// Resolves `host`, connects to it on `port` and performs a handshake for
+    /// `host`.
+    ///
+    /// The resolved addresses are tried alternating between IPv6 and IPv4,
+    /// starting with the family of the first address returned by the
+    /// resolver. A new attempt is started whenever the previous one fails or
+    /// has not finished within 250ms, and the first connection to complete a
+    /// TLS handshake is returned. If every attempt fails, the error holds the
+    /// failure of each address.
+    pub async fn connect_host(
+        &self,
+        host: &str,
+        port: u16,
+    ) -> Result<TlsStream<TcpStream>, ConnectHostError> {
+        let addrs = (host, port)
+            .to_socket_addrs()
+            .await
+            .map_err(ConnectHostError::Resolve)?
+            .collect::<Vec<_>>();
+        let mut addrs = interleave(addrs).into_iter();
+
+        let attempt = |addr: SocketAddr| {
+            async move {
+                let result = match TcpStream::connect(addr).await {
+                    Ok(tcp) => self.connect_verified(host, tcp).await,
+                    Err(e) => Err(ConnectError::Io(e)),
+                };
+                (addr, result)
+            }
+        };
+
+        let mut attempts = FuturesUnordered::new();
+        let mut errors = Vec::new();
+        match addrs.next() {
+            Some(addr) => attempts.push(attempt(addr)),
+            None => {
+                let e = io::Error::new(io::ErrorKind::NotFound, "host has no addresses");
+                return Err(ConnectHostError::Resolve(e));
+            }
+        }
+
+        loop {
+            let finished = if !addrs.as_slice().is_empty() {
+                let delay = Box::pin(task::sleep(ATTEMPT_DELAY));
+                match future::select(attempts.next(), delay).await {
+                    Either::Left((finished, _)) => finished,
+                    Either::Right(((), _)) => {
+                        attempts.push(attempt(addrs.next().unwrap()));
+                        continue;
+                    }
+                }
+            } else {
+                attempts.next().await
+            };
+
+            match finished {
+                Some((_, Ok(stream))) => return Ok(stream),
+                Some((addr, Err(e))) => errors.push((addr, e)),
+                None => {}
+            }
+            // A failed attempt starts the next one straight away.
+            match addrs.next() {
+                Some(addr) => attempts.push(attempt(addr)),
+                None if attempts.is_empty() => return Err(ConnectHostError::Attempts(errors)),
+                None => {}
+            }
+        }
+    }
+}
+
+/// Orders addresses by alternating address family, starting with the family
+/// of the first address.
+fn interleave(addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let prefer_v6 = addrs.first().is_some_and(SocketAddr::is_ipv6);
+    let (v6, v4): (Vec<_>, Vec<_>) = addrs.into_iter().partition(SocketAddr::is_ipv6);
+    let (first, second) = if prefer_v6 { (v6, v4) } else { (v4, v6) };
+
+    let mut ordered = Vec::with_capacity(first.len() + second.len());
+    let mut first = first.into_iter();
+    let mut second = second.into_iter();
+    loop {
+        match (first.next(), second.next()) {
+            (None, None) => return ordered,
+            (a, b) => {
+                ordered.extend(a);
+                ordered.extend(b);
+            }
+        }
+    }
+}
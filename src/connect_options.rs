@@ -0,0 +1,180 @@
+//! Per-connection overrides of a connector's settings.
+
+use std::fmt;
+
+use futures::io::{AsyncRead, AsyncWrite};
+
+use crate::{ConnectError, Identity, ServerName, TlsConnector, TlsStream};
+
+/// Settings for a single `TlsConnector::connect_with` call.
+///
+/// Anything not set here is taken from the connector.
+#[derive(Clone)]
+pub struct ConnectOptions {
+    name: ServerName,
+    sni: Sni,
+    alpn: Option<Vec<String>>,
+    identity: Option<Identity>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Sni {
+    Default,
+    Disabled,
+    #[cfg(feature = "x509")]
+    Name(String),
+}
+
+impl ConnectOptions {
+    /// Creates options that verify the server's certificate against `name`
+    /// and otherwise connect like the connector would.
+    pub fn new(name: ServerName) -> ConnectOptions {
+        ConnectOptions {
+            name,
+            sni: Sni::Default,
+            alpn: None,
+            identity: None,
+        }
+    }
+
+    /// Sends no SNI, still verifying the certificate against the name given
+    /// to `new`. Useful for backends reached by address that serve a single
+    /// certificate.
+    pub fn disable_sni(&mut self) -> &mut ConnectOptions {
+        self.sni = Sni::Disabled;
+        self
+    }
+
+    /// Sends `sni` as the server name instead of the name given to `new`,
+    /// which is still the name the certificate must be valid for.
+    ///
+    /// `native-tls` verifies the name it sends, so when the two differ the
+    /// handshake skips hostname verification and the certificate is checked
+    /// against the verification name afterwards, failing with
+    /// `ConnectError::NameMismatch`. `sni` must be a DNS name; anything else
+    /// fails with `ConnectError::InvalidServerName` before anything is sent.
+    ///
+    /// Requires the `x509` feature, which parses the certificate for that
+    /// check; without it a differing name could not be verified at all.
+    #[cfg(feature = "x509")]
+    pub fn sni(&mut self, sni: &str) -> &mut ConnectOptions {
+        self.sni = Sni::Name(sni.to_owned());
+        self
+    }
+
+    /// Offers `protocols` with ALPN, in order of preference, instead of the
+    /// ones set with `TlsConnectorBuilder::request_alpns`. The server's
+    /// choice is available from `TlsStream::negotiated_alpn`.
+    pub fn alpn(&mut self, protocols: &[&str]) -> &mut ConnectOptions {
+        self.alpn = Some(protocols.iter().map(|p| (*p).to_owned()).collect());
+        self
+    }
+
+    /// Presents `identity` as the client certificate instead of the
+    /// connector's.
+    pub fn identity(&mut self, identity: Identity) -> &mut ConnectOptions {
+        self.identity = Some(identity);
+        self
+    }
+
+    fn overrides_connector(&self) -> bool {
+        self.sni != Sni::Default || self.alpn.is_some() || self.identity.is_some()
+    }
+}
+
+impl fmt::Debug for ConnectOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConnectOptions")
+            .field("name", &self.name)
+            .field("sni", &self.sni)
+            .field("alpn", &self.alpn)
+            .field("identity", &self.identity.is_some())
+            .finish()
+    }
+}
+
+impl TlsConnector {
+    /// Connects the provided stream like `connect_to`, with `options`
+    /// overriding the connector's SNI, ALPN and client identity for this
+    /// connection only.
+    ///
+    /// Options that override the connector build a new `native-tls`
+    /// connector for the call, which costs as much as `TlsConnectorBuilder::build`.
+    /// Connectors created from a `native_tls::TlsConnector` rather than a
+    /// builder cannot be overridden, and fail with
+    /// `ConnectError::OptionsUnsupported`.
+    pub async fn connect_with<S>(
+        &self,
+        options: &ConnectOptions,
+        stream: S,
+    ) -> Result<TlsStream<S>, ConnectError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        if !options.overrides_connector() {
+            return self.connect_to(&options.name, stream).await;
+        }
+        let mut config = match &self.config {
+            Some(config) => (**config).clone(),
+            None => return Err(ConnectError::OptionsUnsupported),
+        };
+        if let Some(alpn) = &options.alpn {
+            config.alpn = alpn.clone();
+        }
+        if let Some(identity) = &options.identity {
+            config.identity = Some(identity.clone());
+        }
+
+        #[cfg(feature = "x509")]
+        let mut verify_after = false;
+        let (domain, use_sni) = match &options.sni {
+            Sni::Default => (
+                options.name.to_domain(),
+                config.use_sni && matches!(options.name, ServerName::Dns(_)),
+            ),
+            Sni::Disabled => (options.name.to_domain(), false),
+            #[cfg(feature = "x509")]
+            Sni::Name(sni) => {
+                let sni = match sni.parse::<ServerName>()? {
+                    ServerName::Dns(sni) => sni,
+                    ServerName::Ip(_) => {
+                        return Err(ConnectError::InvalidServerName(crate::InvalidServerName {
+                            name: sni.clone(),
+                        }))
+                    }
+                };
+                if ServerName::Dns(sni.clone()) != options.name {
+                    verify_after = !config.accept_invalid_hostnames;
+                    config.accept_invalid_hostnames = true;
+                }
+                (sni, true)
+            }
+        };
+
+        let inner = config.build(use_sni)?;
+        let mut stream = self.connect_native(&inner, &domain, stream).await?;
+        self.checks.check(&mut stream)?;
+        #[cfg(feature = "x509")]
+        {
+            if verify_after {
+                verify_name(&stream, &options.name)?;
+            }
+        }
+        Ok(stream)
+    }
+}
+
+#[cfg(feature = "x509")]
+fn verify_name<S>(stream: &TlsStream<S>, name: &ServerName) -> Result<(), ConnectError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let cert = match stream.peer_certificate()? {
+        Some(cert) => cert,
+        None => return Err(ConnectError::NameMismatch(name.clone())),
+    };
+    match crate::CertificateInfo::parse(&cert) {
+        Ok(info) if info.is_valid_for(name) => Ok(()),
+        _ => Err(ConnectError::NameMismatch(name.clone())),
+    }
+}
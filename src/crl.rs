@@ -0,0 +1,202 @@
+//! Revocation checking against certificate revocation lists.
+//!
+//! `RevocationLists` holds the CRLs published by a private PKI. Attached to a
+//! connector with `TlsConnectorBuilder::revocation_lists`, it makes the
+//! connector refuse servers whose certificate has been revoked. The lists can
+//! be replaced while the connector is in use, for example after fetching a
+//! newer CRL.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+
+use x509_parser::certificate::X509Certificate;
+use x509_parser::pem::Pem;
+use x509_parser::prelude::FromDer;
+use x509_parser::revocation_list::CertificateRevocationList;
+use x509_parser::x509::SubjectPublicKeyInfo;
+
+use crate::x509::system_time;
+use crate::{Certificate, ConnectError, ParseCertificateError, RevocationUnknown, Revoked};
+
+/// A parsed certificate revocation list.
+///
+/// A list is only loaded once its signature has been verified with the key of
+/// the CA that issued it. It then only applies to certificates signed by that
+/// same key, not to everything issued under the same name.
+#[derive(Clone)]
+pub struct RevocationList {
+    issuer: Vec<u8>,
+    issuer_name: String,
+    issuer_key: Vec<u8>,
+    next_update: Option<SystemTime>,
+    serials: HashSet<Vec<u8>>,
+}
+
+impl RevocationList {
+    /// Parses a DER-encoded CRL and verifies that `issuer`, the certificate
+    /// of the CA that published it, signed it.
+    pub fn from_der(
+        der: &[u8],
+        issuer: &Certificate,
+    ) -> Result<RevocationList, ParseCertificateError> {
+        let issuer_der = issuer.to_der().map_err(ParseCertificateError::tls)?;
+        let (_, issuer) =
+            X509Certificate::from_der(&issuer_der).map_err(ParseCertificateError::x509)?;
+        let (_, crl) =
+            CertificateRevocationList::from_der(der).map_err(ParseCertificateError::x509)?;
+        if crl.issuer() != issuer.subject() {
+            return Err(ParseCertificateError::wrong_issuer());
+        }
+        crl.verify_signature(issuer.public_key())
+            .map_err(ParseCertificateError::signature)?;
+
+        Ok(RevocationList {
+            issuer: crl.issuer().as_raw().to_vec(),
+            issuer_name: crl.issuer().to_string(),
+            issuer_key: issuer.public_key().raw.to_vec(),
+            next_update: crl.next_update().map(system_time),
+            serials: crl
+                .iter_revoked_certificates()
+                .map(|revoked| revoked.raw_serial().to_vec())
+                .collect(),
+        })
+    }
+
+    /// Parses and verifies every `X509 CRL` block in a PEM file, all of which
+    /// must have been published by `issuer`.
+    pub fn from_pem(
+        pem: &[u8],
+        issuer: &Certificate,
+    ) -> Result<Vec<RevocationList>, ParseCertificateError> {
+        let mut lists = Vec::new();
+        for block in Pem::iter_from_buffer(pem) {
+            let block = block.map_err(ParseCertificateError::pem)?;
+            if block.label == "X509 CRL" {
+                lists.push(RevocationList::from_der(&block.contents, issuer)?);
+            }
+        }
+        Ok(lists)
+    }
+
+    /// Returns the distinguished name of the CA that issued this list.
+    pub fn issuer(&self) -> &str {
+        &self.issuer_name
+    }
+
+    /// Returns when the issuer promised to publish the next list, if it did.
+    ///
+    /// Once this has passed the list is stale, and servers whose certificates
+    /// it covers are refused until a newer one is loaded.
+    pub fn next_update(&self) -> Option<SystemTime> {
+        self.next_update
+    }
+
+    /// Returns the number of revoked certificates in this list.
+    pub fn len(&self) -> usize {
+        self.serials.len()
+    }
+
+    /// Returns whether this list revokes no certificates.
+    pub fn is_empty(&self) -> bool {
+        self.serials.is_empty()
+    }
+
+    /// Returns whether `cert` was issued by this list's issuer: under the
+    /// same name and signed by the same key.
+    fn issued(&self, cert: &X509Certificate<'_>) -> bool {
+        if cert.issuer().as_raw() != self.issuer.as_slice() {
+            return false;
+        }
+        match SubjectPublicKeyInfo::from_der(&self.issuer_key) {
+            Ok((_, key)) => cert.verify_signature(Some(&key)).is_ok(),
+            Err(_) => false,
+        }
+    }
+}
+
+impl fmt::Debug for RevocationList {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RevocationList")
+            .field("issuer", &self.issuer_name)
+            .field("revoked", &self.serials.len())
+            .finish()
+    }
+}
+
+/// A shared, replaceable set of revocation lists.
+///
+/// Clones share the same lists, so a handle kept after building a connector
+/// can `replace` them and every later handshake sees the new set.
+#[derive(Clone, Default)]
+pub struct RevocationLists {
+    lists: Arc<RwLock<Arc<Vec<RevocationList>>>>,
+}
+
+impl RevocationLists {
+    /// Creates a set holding `lists`.
+    pub fn new(lists: Vec<RevocationList>) -> RevocationLists {
+        RevocationLists {
+            lists: Arc::new(RwLock::new(Arc::new(lists))),
+        }
+    }
+
+    /// Replaces every list in the set with `lists`.
+    pub fn replace(&self, lists: Vec<RevocationList>) {
+        *self.lists.write().unwrap() = Arc::new(lists);
+    }
+
+    /// Checks a DER-encoded certificate against the lists from its issuer.
+    ///
+    /// Fails if the certificate is revoked, if every list from its issuer is
+    /// past its next update, or if there is no certificate to check.
+    pub(crate) fn check(&self, der: Option<&[u8]>) -> Result<(), ConnectError> {
+        let der = der.ok_or_else(|| {
+            ConnectError::RevocationUnknown(RevocationUnknown {
+                reason: "the server sent no certificate".to_owned(),
+            })
+        })?;
+        let (_, cert) = X509Certificate::from_der(der).map_err(|e| {
+            ConnectError::RevocationUnknown(RevocationUnknown {
+                reason: format!(
+                    "invalid server certificate: {}",
+                    ParseCertificateError::x509(e)
+                ),
+            })
+        })?;
+        let serial = cert.raw_serial();
+        let now = SystemTime::now();
+
+        let lists = self.lists.read().unwrap().clone();
+        let mut found = false;
+        let mut current = false;
+        for list in lists.iter().filter(|list| list.issued(&cert)) {
+            if list.serials.contains(serial) {
+                return Err(ConnectError::Revoked(Revoked {
+                    serial: serial.to_vec(),
+                    issuer: cert.issuer().to_string(),
+                }));
+            }
+            found = true;
+            current |= list.next_update.map_or(true, |next| now < next);
+        }
+        if found && !current {
+            return Err(ConnectError::RevocationUnknown(RevocationUnknown {
+                reason: format!(
+                    "the revocation lists from {} are past their next update",
+                    cert.issuer()
+                ),
+            }));
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for RevocationLists {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.lists.read().unwrap().iter())
+            .finish()
+    }
+}
@@ -0,0 +1,118 @@
+//! Loading trust roots from the `SSL_CERT_FILE` and `SSL_CERT_DIR`
+//! environment variables.
+//!
+//! OpenSSL honours these variables but other backends do not, and container
+//! images often keep their bundles in places no backend looks by default.
+
+use std::collections::HashSet;
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::{Certificate, EnvTrustError, TlsConnectorBuilder};
+
+/// What `TlsConnectorBuilder::with_env_trust` loaded.
+#[derive(Clone, Debug, Default)]
+pub struct EnvTrust {
+    loaded: Vec<(PathBuf, usize)>,
+    skipped: Vec<(PathBuf, String)>,
+}
+
+impl EnvTrust {
+    /// Returns each file that certificates were loaded from, with the number
+    /// of certificates it held, in the order they were read.
+    pub fn loaded(&self) -> &[(PathBuf, usize)] {
+        &self.loaded
+    }
+
+    /// Returns each file in an `SSL_CERT_DIR` directory that was not loaded,
+    /// with the reason.
+    pub fn skipped(&self) -> &[(PathBuf, String)] {
+        &self.skipped
+    }
+}
+
+impl TlsConnectorBuilder {
+    /// Adds the trust roots named by the `SSL_CERT_FILE` and `SSL_CERT_DIR`
+    /// environment variables to the set the connector trusts.
+    ///
+    /// `SSL_CERT_FILE` names a PEM bundle, and `SSL_CERT_DIR` a list of
+    /// directories separated like `PATH`, each holding PEM files. Unset or
+    /// empty variables are ignored. The roots are added to the built-in ones;
+    /// call `disable_built_in_roots` to trust only these.
+    ///
+    /// A path that cannot be read, or an `SSL_CERT_FILE` that holds no
+    /// certificates, is an error. Files in a directory that hold no
+    /// certificates, such as revocation lists or notes, are skipped and listed
+    /// in the returned report, as are links to a file already loaded. On
+    /// error no roots are added.
+    pub fn with_env_trust(&mut self) -> Result<EnvTrust, EnvTrustError> {
+        let mut report = EnvTrust::default();
+        let mut seen = HashSet::new();
+        let mut roots = Vec::new();
+
+        if let Some(file) = var("SSL_CERT_FILE") {
+            let path = PathBuf::from(file);
+            let certs = read_bundle(&path)?.map_err(|e| EnvTrustError::Invalid(path.clone(), e))?;
+            if certs.is_empty() {
+                return Err(EnvTrustError::Empty(path));
+            }
+            seen.insert(fs::canonicalize(&path).unwrap_or_else(|_| path.clone()));
+            report.loaded.push((path, certs.len()));
+            roots.extend(certs);
+        }
+
+        if let Some(dirs) = var("SSL_CERT_DIR") {
+            for dir in env::split_paths(&dirs) {
+                let entries =
+                    fs::read_dir(&dir).map_err(|e| EnvTrustError::Read(dir.clone(), e))?;
+                let mut paths = Vec::new();
+                for entry in entries {
+                    let entry = entry.map_err(|e| EnvTrustError::Read(dir.clone(), e))?;
+                    paths.push(entry.path());
+                }
+                paths.sort();
+
+                for path in paths {
+                    if !path.is_file() {
+                        continue;
+                    }
+                    let canonical = fs::canonicalize(&path)
+                        .map_err(|e| EnvTrustError::Read(path.clone(), e))?;
+                    if !seen.insert(canonical) {
+                        report
+                            .skipped
+                            .push((path, "same file as one already loaded".to_owned()));
+                        continue;
+                    }
+                    match read_bundle(&path)? {
+                        Ok(ref certs) if certs.is_empty() => {
+                            report.skipped.push((path, "no certificates".to_owned()));
+                        }
+                        Ok(certs) => {
+                            report.loaded.push((path, certs.len()));
+                            roots.extend(certs);
+                        }
+                        Err(e) => report.skipped.push((path, e.to_string())),
+                    }
+                }
+            }
+        }
+
+        for cert in roots {
+            self.add_root_certificate(cert);
+        }
+        Ok(report)
+    }
+}
+
+fn var(name: &str) -> Option<OsString> {
+    env::var_os(name).filter(|value| !value.is_empty())
+}
+
+/// Reads a PEM file, failing only if it cannot be read.
+fn read_bundle(path: &Path) -> Result<Result<Vec<Certificate>, crate::Error>, EnvTrustError> {
+    let pem = fs::read(path).map_err(|e| EnvTrustError::Read(path.to_owned(), e))?;
+    Ok(Certificate::stack_from_pem(&pem))
+}
@@ -0,0 +1,489 @@
+use std::error::Error as StdError;
+use std::fmt;
+use std::io;
+use std::net::IpAddr;
+#[cfg(feature = "runtime")]
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+use crate::{Error, ServerName};
+
+/// An error from opening a transport and then performing a TLS handshake on
+/// it.
+#[derive(Debug)]
+pub enum ConnectError {
+    /// The transport could not be opened.
+    Io(io::Error),
+    /// The TLS handshake failed.
+    Tls(Error),
+    /// The handshake completed, but the server's certificate has been revoked
+    /// by one of the connector's revocation lists.
+    Revoked(Revoked),
+    /// The handshake completed, but whether the server's certificate has been
+    /// revoked could not be determined, so it was refused.
+    RevocationUnknown(RevocationUnknown),
+    /// The handshake completed, but the server's SPIFFE ID is not the one the
+    /// connector expects.
+    SpiffeMismatch(SpiffeMismatch),
+    /// The server name is neither a valid DNS name nor an IP address. Nothing
+    /// was sent to the server.
+    InvalidServerName(InvalidServerName),
+    /// The handshake completed, but the server's certificate is not valid for
+    /// the name it was verified against.
+    NameMismatch(ServerName),
+    /// The connector was converted from a `native_tls::TlsConnector`, so it
+    /// cannot apply the settings `connect_with` was given. Nothing was sent
+    /// to the server.
+    OptionsUnsupported,
+}
+
+impl fmt::Display for ConnectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConnectError::Io(e) => write!(f, "transport error: {}", e),
+            ConnectError::Tls(e) => write!(f, "tls error: {}", e),
+            ConnectError::Revoked(e) => e.fmt(f),
+            ConnectError::RevocationUnknown(e) => e.fmt(f),
+            ConnectError::SpiffeMismatch(e) => e.fmt(f),
+            ConnectError::InvalidServerName(e) => e.fmt(f),
+            ConnectError::NameMismatch(name) => {
+                write!(f, "server certificate is not valid for {}", name)
+            }
+            ConnectError::OptionsUnsupported => write!(
+                f,
+                "connector was not built by a TlsConnectorBuilder and cannot be overridden"
+            ),
+        }
+    }
+}
+
+impl StdError for ConnectError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            ConnectError::Io(e) => Some(e),
+            ConnectError::Tls(e) => Some(e),
+            ConnectError::Revoked(e) => Some(e),
+            ConnectError::RevocationUnknown(e) => Some(e),
+            ConnectError::SpiffeMismatch(e) => Some(e),
+            ConnectError::InvalidServerName(e) => Some(e),
+            ConnectError::NameMismatch(_) | ConnectError::OptionsUnsupported => None,
+        }
+    }
+}
+
+/// A peer certificate found in a revocation list.
+#[derive(Clone, Debug)]
+pub struct Revoked {
+    pub(crate) serial: Vec<u8>,
+    pub(crate) issuer: String,
+}
+
+impl Revoked {
+    /// Returns the serial number of the revoked certificate as big-endian
+    /// bytes.
+    pub fn serial(&self) -> &[u8] {
+        &self.serial
+    }
+
+    /// Returns the distinguished name of the certificate's issuer.
+    pub fn issuer(&self) -> &str {
+        &self.issuer
+    }
+}
+
+impl fmt::Display for Revoked {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "certificate with serial ")?;
+        for (i, b) in self.serial.iter().enumerate() {
+            if i > 0 {
+                write!(f, ":")?;
+            }
+            write!(f, "{:02X}", b)?;
+        }
+        write!(f, " issued by {} has been revoked", self.issuer)
+    }
+}
+
+impl StdError for Revoked {}
+
+/// A peer certificate whose revocation status could not be determined.
+#[derive(Clone, Debug)]
+pub struct RevocationUnknown {
+    pub(crate) reason: String,
+}
+
+impl fmt::Display for RevocationUnknown {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "revocation status unknown: {}", self.reason)
+    }
+}
+
+impl StdError for RevocationUnknown {}
+
+/// A server whose SPIFFE ID did not match the expected one.
+#[derive(Clone, Debug)]
+pub struct SpiffeMismatch {
+    pub(crate) expected: String,
+    pub(crate) found: Option<String>,
+}
+
+impl SpiffeMismatch {
+    /// Returns the SPIFFE ID the server presented, or `None` if its
+    /// certificate did not hold exactly one.
+    pub fn found(&self) -> Option<&str> {
+        self.found.as_deref()
+    }
+}
+
+impl fmt::Display for SpiffeMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.found {
+            Some(id) => write!(f, "server has SPIFFE ID {}, expected {}", id, self.expected),
+            None => write!(
+                f,
+                "server certificate has no single SPIFFE ID, expected {}",
+                self.expected
+            ),
+        }
+    }
+}
+
+impl StdError for SpiffeMismatch {}
+
+/// A server name that is neither a valid DNS name nor an IP address.
+#[derive(Clone, Debug)]
+pub struct InvalidServerName {
+    pub(crate) name: String,
+}
+
+impl InvalidServerName {
+    /// Returns the rejected name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl fmt::Display for InvalidServerName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid server name {:?}", self.name)
+    }
+}
+
+impl StdError for InvalidServerName {}
+
+impl From<InvalidServerName> for ConnectError {
+    fn from(e: InvalidServerName) -> ConnectError {
+        ConnectError::InvalidServerName(e)
+    }
+}
+
+impl From<io::Error> for ConnectError {
+    fn from(e: io::Error) -> ConnectError {
+        ConnectError::Io(e)
+    }
+}
+
+impl From<Error> for ConnectError {
+    fn from(e: Error) -> ConnectError {
+        ConnectError::Tls(e)
+    }
+}
+
+/// An error from accepting a connection whose first bytes are inspected
+/// before the handshake, as by `TlsAcceptor::accept_proxied` and
+/// `StartHandshake::read`.
+#[derive(Debug)]
+pub enum AcceptError {
+    /// Reading from the transport failed.
+    Io(io::Error),
+    /// The TLS handshake failed.
+    Tls(Error),
+    /// A trusted peer sent a malformed PROXY protocol header, or none when
+    /// one is required.
+    InvalidProxyHeader(InvalidProxyHeader),
+    /// A peer the `ProxyPolicy` does not trust sent a PROXY protocol header.
+    UntrustedProxy(IpAddr),
+    /// The client did not start with a well-formed ClientHello.
+    InvalidClientHello(InvalidClientHello),
+}
+
+impl fmt::Display for AcceptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AcceptError::Io(e) => write!(f, "transport error: {}", e),
+            AcceptError::Tls(e) => write!(f, "tls error: {}", e),
+            AcceptError::InvalidProxyHeader(e) => e.fmt(f),
+            AcceptError::UntrustedProxy(ip) => {
+                write!(f, "PROXY protocol header from untrusted peer {}", ip)
+            }
+            AcceptError::InvalidClientHello(e) => e.fmt(f),
+        }
+    }
+}
+
+impl StdError for AcceptError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            AcceptError::Io(e) => Some(e),
+            AcceptError::Tls(e) => Some(e),
+            AcceptError::InvalidProxyHeader(e) => Some(e),
+            AcceptError::UntrustedProxy(_) => None,
+            AcceptError::InvalidClientHello(e) => Some(e),
+        }
+    }
+}
+
+impl From<io::Error> for AcceptError {
+    fn from(e: io::Error) -> AcceptError {
+        AcceptError::Io(e)
+    }
+}
+
+impl From<Error> for AcceptError {
+    fn from(e: Error) -> AcceptError {
+        AcceptError::Tls(e)
+    }
+}
+
+/// A PROXY protocol header that could not be parsed.
+#[derive(Clone, Debug)]
+pub struct InvalidProxyHeader {
+    pub(crate) reason: &'static str,
+}
+
+impl fmt::Display for InvalidProxyHeader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid PROXY protocol header: {}", self.reason)
+    }
+}
+
+impl StdError for InvalidProxyHeader {}
+
+/// A ClientHello that could not be parsed.
+#[derive(Clone, Debug)]
+pub struct InvalidClientHello {
+    pub(crate) reason: &'static str,
+}
+
+impl fmt::Display for InvalidClientHello {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid ClientHello: {}", self.reason)
+    }
+}
+
+impl StdError for InvalidClientHello {}
+
+/// An error from `TlsConnectorBuilder::with_env_trust`.
+#[derive(Debug)]
+pub enum EnvTrustError {
+    /// A file or directory named by `SSL_CERT_FILE` or `SSL_CERT_DIR` could
+    /// not be read.
+    Read(PathBuf, io::Error),
+    /// The file named by `SSL_CERT_FILE` is not a PEM certificate bundle.
+    Invalid(PathBuf, Error),
+    /// The file named by `SSL_CERT_FILE` holds no certificates.
+    Empty(PathBuf),
+}
+
+impl fmt::Display for EnvTrustError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EnvTrustError::Read(path, e) => write!(f, "failed to read {}: {}", path.display(), e),
+            EnvTrustError::Invalid(path, e) => {
+                write!(f, "invalid certificate bundle {}: {}", path.display(), e)
+            }
+            EnvTrustError::Empty(path) => write!(f, "no certificates in {}", path.display()),
+        }
+    }
+}
+
+impl StdError for EnvTrustError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            EnvTrustError::Read(_, e) => Some(e),
+            EnvTrustError::Invalid(_, e) => Some(e),
+            EnvTrustError::Empty(_) => None,
+        }
+    }
+}
+
+/// An error from `TlsConnector::connect_host`.
+#[cfg(feature = "runtime")]
+#[derive(Debug)]
+pub enum ConnectHostError {
+    /// The host name could not be resolved to any address.
+    Resolve(io::Error),
+    /// Every resolved address was tried and none completed a handshake. Holds
+    /// the failure of each address in the order the attempts finished.
+    Attempts(Vec<(SocketAddr, ConnectError)>),
+}
+
+#[cfg(feature = "runtime")]
+impl fmt::Display for ConnectHostError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConnectHostError::Resolve(e) => write!(f, "failed to resolve host: {}", e),
+            ConnectHostError::Attempts(attempts) => {
+                write!(f, "all {} connection attempts failed", attempts.len())?;
+                for (addr, e) in attempts {
+                    write!(f, "; {}: {}", addr, e)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(feature = "runtime")]
+impl StdError for ConnectHostError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            ConnectHostError::Resolve(e) => Some(e),
+            ConnectHostError::Attempts(attempts) => {
+                attempts.last().map(|(_, e)| e as &(dyn StdError + 'static))
+            }
+        }
+    }
+}
+
+/// An error from generating a certificate with the `pki` module.
+#[cfg(feature = "pki")]
+#[derive(Debug)]
+pub struct GenerateError(GenerateErrorKind);
+
+#[cfg(feature = "pki")]
+#[derive(Debug)]
+enum GenerateErrorKind {
+    Certificate(rcgen::Error),
+    Tls(Error),
+}
+
+#[cfg(feature = "pki")]
+impl fmt::Display for GenerateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.0 {
+            GenerateErrorKind::Certificate(e) => write!(f, "failed to generate certificate: {}", e),
+            GenerateErrorKind::Tls(e) => write!(f, "failed to load generated identity: {}", e),
+        }
+    }
+}
+
+#[cfg(feature = "pki")]
+impl StdError for GenerateError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match &self.0 {
+            GenerateErrorKind::Certificate(e) => Some(e),
+            GenerateErrorKind::Tls(e) => Some(e),
+        }
+    }
+}
+
+#[cfg(feature = "pki")]
+impl From<rcgen::Error> for GenerateError {
+    fn from(e: rcgen::Error) -> GenerateError {
+        GenerateError(GenerateErrorKind::Certificate(e))
+    }
+}
+
+#[cfg(feature = "pki")]
+impl From<rcgen::InvalidAsn1String> for GenerateError {
+    fn from(e: rcgen::InvalidAsn1String) -> GenerateError {
+        GenerateError(GenerateErrorKind::Certificate(
+            rcgen::Error::InvalidAsn1String(e),
+        ))
+    }
+}
+
+#[cfg(feature = "pki")]
+impl From<Error> for GenerateError {
+    fn from(e: Error) -> GenerateError {
+        GenerateError(GenerateErrorKind::Tls(e))
+    }
+}
+
+/// An error from parsing a certificate, or from parsing and verifying a
+/// certificate revocation list.
+#[cfg(feature = "x509")]
+#[derive(Debug)]
+pub struct ParseCertificateError(ParseCertificateErrorKind);
+
+#[cfg(feature = "x509")]
+#[derive(Debug)]
+enum ParseCertificateErrorKind {
+    X509(x509_parser::error::X509Error),
+    Pem(x509_parser::error::PEMError),
+    Tls(Error),
+    Handshake(Error),
+    WrongIssuer,
+    Signature(x509_parser::error::X509Error),
+}
+
+#[cfg(feature = "x509")]
+impl ParseCertificateError {
+    pub(crate) fn x509<E>(e: E) -> ParseCertificateError
+    where
+        E: Into<x509_parser::nom::Err<x509_parser::error::X509Error>>,
+    {
+        let e = match e.into() {
+            x509_parser::nom::Err::Error(e) | x509_parser::nom::Err::Failure(e) => e,
+            x509_parser::nom::Err::Incomplete(_) => {
+                x509_parser::error::X509Error::InvalidCertificate
+            }
+        };
+        ParseCertificateError(ParseCertificateErrorKind::X509(e))
+    }
+
+    pub(crate) fn pem(e: x509_parser::error::PEMError) -> ParseCertificateError {
+        ParseCertificateError(ParseCertificateErrorKind::Pem(e))
+    }
+
+    pub(crate) fn tls(e: Error) -> ParseCertificateError {
+        ParseCertificateError(ParseCertificateErrorKind::Tls(e))
+    }
+
+    pub(crate) fn handshake(e: Error) -> ParseCertificateError {
+        ParseCertificateError(ParseCertificateErrorKind::Handshake(e))
+    }
+
+    pub(crate) fn wrong_issuer() -> ParseCertificateError {
+        ParseCertificateError(ParseCertificateErrorKind::WrongIssuer)
+    }
+
+    pub(crate) fn signature(e: x509_parser::error::X509Error) -> ParseCertificateError {
+        ParseCertificateError(ParseCertificateErrorKind::Signature(e))
+    }
+}
+
+#[cfg(feature = "x509")]
+impl fmt::Display for ParseCertificateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.0 {
+            ParseCertificateErrorKind::X509(e) => write!(f, "invalid certificate: {}", e),
+            ParseCertificateErrorKind::Pem(e) => write!(f, "invalid PEM: {}", e),
+            ParseCertificateErrorKind::Tls(e) => write!(f, "failed to encode certificate: {}", e),
+            ParseCertificateErrorKind::Handshake(e) => {
+                write!(f, "handshake to read the certificate failed: {}", e)
+            }
+            ParseCertificateErrorKind::WrongIssuer => {
+                write!(f, "revocation list was not issued by the given certificate")
+            }
+            ParseCertificateErrorKind::Signature(e) => {
+                write!(f, "invalid revocation list signature: {}", e)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "x509")]
+impl StdError for ParseCertificateError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match &self.0 {
+            ParseCertificateErrorKind::X509(e) => Some(e),
+            ParseCertificateErrorKind::Pem(e) => Some(e),
+            ParseCertificateErrorKind::Tls(e) => Some(e),
+            ParseCertificateErrorKind::Handshake(e) => Some(e),
+            ParseCertificateErrorKind::WrongIssuer => None,
+            ParseCertificateErrorKind::Signature(e) => Some(e),
+        }
+    }
+}
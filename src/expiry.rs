@@ -0,0 +1,92 @@
+//! Checking how close certificates are to expiring.
+//!
+//! A certificate that expires takes every connection relying on it down with
+//! it. These checks are meant to be run at startup and then periodically, so
+//! an `Observer` or a log warns well before that happens.
+
+use std::time::{Duration, SystemTime};
+
+use crate::observer::Observer;
+use crate::x509::CertificateInfo;
+use crate::{Certificate, ParseCertificateError, TlsAcceptor};
+
+/// The result of checking a certificate against an expiry threshold.
+#[derive(Clone, Debug)]
+pub struct Expiry {
+    info: CertificateInfo,
+    threshold: Duration,
+    checked_at: SystemTime,
+}
+
+impl Expiry {
+    /// Returns the checked certificate, including its validity window.
+    pub fn certificate(&self) -> &CertificateInfo {
+        &self.info
+    }
+
+    /// Returns how long the certificate remained valid for when it was
+    /// checked, or `None` if it had already expired.
+    pub fn remaining(&self) -> Option<Duration> {
+        self.info.not_after().duration_since(self.checked_at).ok()
+    }
+
+    /// Returns whether the certificate had expired when it was checked.
+    pub fn is_expired(&self) -> bool {
+        self.remaining().is_none()
+    }
+
+    /// Returns whether the certificate had expired or was due to expire
+    /// within the threshold when it was checked.
+    pub fn is_expiring(&self) -> bool {
+        self.remaining()
+            .map_or(true, |remaining| remaining <= self.threshold)
+    }
+}
+
+impl TlsAcceptor {
+    /// Checks whether the certificate this acceptor presents expires within
+    /// `threshold`.
+    ///
+    /// If it does, or it has already expired, the acceptor's observer is told
+    /// through `Observer::certificate_expiring` and, with the `tracing`
+    /// feature, a warning is logged.
+    pub async fn check_expiry(&self, threshold: Duration) -> Result<Expiry, ParseCertificateError> {
+        let cert = self
+            .certificate()
+            .await
+            .map_err(ParseCertificateError::handshake)?;
+        check_certificate_expiry(&cert, threshold, &*self.settings.observer)
+    }
+}
+
+/// Checks whether `cert` expires within `threshold`.
+///
+/// This is meant for certificates the crate cannot get at itself, such as the
+/// one in a client `Identity`, which is opaque and only sent to servers that
+/// ask for it. An expiring certificate is reported to `observer` and logged
+/// like `TlsAcceptor::check_expiry` does.
+pub fn check_certificate_expiry(
+    cert: &Certificate,
+    threshold: Duration,
+    observer: &dyn Observer,
+) -> Result<Expiry, ParseCertificateError> {
+    let expiry = Expiry {
+        info: CertificateInfo::parse(cert)?,
+        threshold,
+        checked_at: SystemTime::now(),
+    };
+    if expiry.is_expiring() {
+        let info = expiry.certificate();
+        observer.certificate_expiring(info.subject(), info.not_after());
+        #[cfg(feature = "tracing")]
+        match expiry.remaining() {
+            Some(remaining) => tracing::warn!(
+                subject = info.subject(),
+                remaining_secs = remaining.as_secs(),
+                "certificate expires soon"
+            ),
+            None => tracing::warn!(subject = info.subject(), "certificate has expired"),
+        }
+    }
+    Ok(expiry)
+}
@@ -17,19 +17,102 @@
 //! functionality provided by the `native-tls` crate, on which this crate is
 //! built. Configuration of TLS parameters is still primarily done through the
 //! `native-tls` crate.
+//!
+//! The `runtime` feature adds `TlsConnector::connect_host`, which resolves a
+//! host name and races TCP connections to its addresses using `async-std`.
+//!
+//! The `pki` feature adds `CertificateAuthority`, which generates a throwaway
+//! root and issues leaf certificates from it for development and tests.
+//!
+//! The `x509` feature adds `CertificateInfo`, a parsed view of a certificate's
+//! names, validity, key and fingerprint, and `TlsAcceptor::check_expiry`,
+//! which warns when the acceptor's certificate is close to expiring. It also
+//! enables the checks `TlsConnector::connect_verified` makes after the
+//! handshake: revocation lists, SPIFFE IDs, and the certificate name when
+//! `ConnectOptions::sni` sends a different one.
+//!
+//! With the `tracing` feature enabled, every `connect` and `accept` runs in a
+//! `tracing` span that records each handshake round trip that would block and
+//! the final outcome.
 
-use futures::io::{AsyncRead, AsyncWrite, Initializer};
+use futures::io::{AsyncBufRead, AsyncRead, AsyncWrite, Initializer, IoSlice};
 use native_tls::{HandshakeError, MidHandshakeTlsStream};
+use std::cmp;
 use std::fmt;
 use std::future::Future;
 use std::io::{self, Read, Write};
 use std::marker::Unpin;
+use std::mem;
 use std::pin::Pin;
 use std::ptr::null_mut;
+use std::sync::{Arc, OnceLock};
 use std::task::{Context, Poll};
+use std::time::Instant;
 
 pub use native_tls::{Certificate, Error, Identity, Protocol};
 
+pub mod client_hello;
+#[cfg(feature = "runtime")]
+mod connect_host;
+mod connect_options;
+#[cfg(feature = "x509")]
+pub mod crl;
+mod env_trust;
+mod error;
+#[cfg(feature = "x509")]
+mod expiry;
+pub mod observer;
+#[cfg(feature = "pki")]
+pub mod pki;
+pub mod pool;
+pub mod proxy;
+pub mod proxy_protocol;
+mod rewind;
+mod server_name;
+#[cfg(feature = "x509")]
+pub mod spiffe;
+#[cfg(feature = "testing")]
+pub mod testing;
+#[cfg(not(feature = "testing"))]
+mod testing;
+#[cfg(feature = "tracing")]
+mod trace;
+#[cfg(feature = "x509")]
+pub mod x509;
+
+pub use crate::client_hello::{ClientHello, StartHandshake};
+pub use crate::connect_options::ConnectOptions;
+#[cfg(feature = "x509")]
+pub use crate::crl::{RevocationList, RevocationLists};
+pub use crate::env_trust::EnvTrust;
+#[cfg(feature = "runtime")]
+pub use crate::error::ConnectHostError;
+#[cfg(feature = "pki")]
+pub use crate::error::GenerateError;
+#[cfg(feature = "x509")]
+pub use crate::error::ParseCertificateError;
+pub use crate::error::{
+    AcceptError, ConnectError, EnvTrustError, InvalidClientHello, InvalidProxyHeader,
+    InvalidServerName, RevocationUnknown, Revoked, SpiffeMismatch,
+};
+#[cfg(feature = "x509")]
+pub use crate::expiry::{check_certificate_expiry, Expiry};
+pub use crate::observer::{CountingObserver, ErrorCategory, NoopObserver, Observer};
+#[cfg(feature = "pki")]
+pub use crate::pki::{CertificateAuthority, IssuedCertificate};
+pub use crate::pool::{Pooled, TlsPool, TlsPoolBuilder};
+pub use crate::proxy::{Credentials, Proxy};
+pub use crate::proxy_protocol::{ProxyHeader, ProxyPolicy};
+pub use crate::rewind::Rewind;
+pub use crate::server_name::ServerName;
+#[cfg(feature = "x509")]
+pub use crate::spiffe::SpiffeMatch;
+#[cfg(feature = "x509")]
+pub use crate::x509::CertificateInfo;
+
+/// The largest amount of plaintext that fits in a single TLS record.
+const MAX_RECORD_PLAINTEXT: usize = 16 * 1024;
+
 #[derive(Debug)]
 struct AllowStd<S> {
     inner: S,
@@ -43,23 +126,153 @@ struct AllowStd<S> {
 /// and both the server and the client are ready for receiving and sending
 /// data. Bytes read from a `TlsStream` are decrypted from `S` and bytes written
 /// to a `TlsStream` are encrypted when passing through to `S`.
-#[derive(Debug)]
-pub struct TlsStream<S>(native_tls::TlsStream<AllowStd<S>>);
+///
+/// `TlsStream` also implements `AsyncBufRead`, holding up to one record of
+/// decrypted plaintext, so line-based protocols can read from it directly
+/// instead of wrapping it in another buffered reader.
+pub struct TlsStream<S> {
+    inner: native_tls::TlsStream<AllowStd<S>>,
+    settings: StreamSettings,
+    closed: bool,
+    write_buf: Vec<u8>,
+    read_buf: Vec<u8>,
+    read_pos: usize,
+    read_cap: usize,
+    spiffe_id: Option<String>,
+    proxy_header: Option<ProxyHeader>,
+}
 
 /// A wrapper around a `native_tls::TlsConnector`, providing an async `connect`
 /// method.
 #[derive(Clone)]
-pub struct TlsConnector(native_tls::TlsConnector);
+pub struct TlsConnector {
+    inner: native_tls::TlsConnector,
+    /// A variant of `inner` that sends no SNI, for IP addresses, built on
+    /// first use when `inner` sends SNI.
+    no_sni: Arc<OnceLock<native_tls::TlsConnector>>,
+    config: Option<Arc<ConnectorConfig>>,
+    settings: StreamSettings,
+    checks: PeerChecks,
+}
 
 /// A wrapper around a `native_tls::TlsAcceptor`, providing an async `accept`
 /// method.
 #[derive(Clone)]
-pub struct TlsAcceptor(native_tls::TlsAcceptor);
+pub struct TlsAcceptor {
+    inner: native_tls::TlsAcceptor,
+    settings: StreamSettings,
+}
+
+/// Per-stream settings shared by a connector or acceptor with the streams it
+/// creates.
+#[derive(Clone)]
+struct StreamSettings {
+    observer: Arc<dyn Observer>,
+    max_send_fragment: usize,
+}
+
+impl Default for StreamSettings {
+    fn default() -> StreamSettings {
+        StreamSettings {
+            observer: Arc::new(NoopObserver),
+            max_send_fragment: MAX_RECORD_PLAINTEXT,
+        }
+    }
+}
+
+/// The `native-tls` settings of a connector, kept so that variants of it can
+/// be built.
+#[derive(Clone)]
+struct ConnectorConfig {
+    identity: Option<Identity>,
+    min_protocol: Option<Protocol>,
+    max_protocol: Option<Protocol>,
+    roots: Vec<Certificate>,
+    disable_built_in_roots: bool,
+    accept_invalid_certs: bool,
+    accept_invalid_hostnames: bool,
+    use_sni: bool,
+    alpn: Vec<String>,
+}
+
+impl Default for ConnectorConfig {
+    fn default() -> ConnectorConfig {
+        ConnectorConfig {
+            identity: None,
+            min_protocol: Some(Protocol::Tlsv10),
+            max_protocol: None,
+            roots: Vec::new(),
+            disable_built_in_roots: false,
+            accept_invalid_certs: false,
+            accept_invalid_hostnames: false,
+            use_sni: true,
+            alpn: Vec::new(),
+        }
+    }
+}
+
+impl ConnectorConfig {
+    fn build(&self, use_sni: bool) -> Result<native_tls::TlsConnector, Error> {
+        let mut builder = native_tls::TlsConnector::builder();
+        if let Some(identity) = &self.identity {
+            builder.identity(identity.clone());
+        }
+        for cert in &self.roots {
+            builder.add_root_certificate(cert.clone());
+        }
+        let alpn = self.alpn.iter().map(String::as_str).collect::<Vec<_>>();
+        builder
+            .request_alpns(&alpn)
+            .min_protocol_version(self.min_protocol)
+            .max_protocol_version(self.max_protocol)
+            .disable_built_in_roots(self.disable_built_in_roots)
+            .danger_accept_invalid_certs(self.accept_invalid_certs)
+            .danger_accept_invalid_hostnames(self.accept_invalid_hostnames)
+            .use_sni(use_sni)
+            .build()
+    }
+}
+
+/// Checks applied to the server after a connector's handshake completes.
+#[derive(Clone, Default)]
+struct PeerChecks {
+    #[cfg(feature = "x509")]
+    revocation: Option<RevocationLists>,
+    #[cfg(feature = "x509")]
+    spiffe: Option<SpiffeMatch>,
+}
+
+impl PeerChecks {
+    fn check<S>(&self, stream: &mut TlsStream<S>) -> Result<(), ConnectError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        #[cfg(feature = "x509")]
+        {
+            if self.revocation.is_none() && self.spiffe.is_none() {
+                return Ok(());
+            }
+            let der = stream
+                .peer_certificate()?
+                .map(|cert| cert.to_der())
+                .transpose()?;
+            if let Some(revocation) = &self.revocation {
+                revocation.check(der.as_deref())?;
+            }
+            if let Some(expected) = &self.spiffe {
+                stream.spiffe_id = Some(spiffe::verify(expected, der.as_deref())?);
+            }
+        }
+        #[cfg(not(feature = "x509"))]
+        let _ = stream;
+        Ok(())
+    }
+}
 
 struct MidHandshake<S>(Option<MidHandshakeTlsStream<AllowStd<S>>>);
 
 enum StartedHandshake<S> {
-    Done(TlsStream<S>),
+    Done(native_tls::TlsStream<AllowStd<S>>),
     Mid(MidHandshakeTlsStream<AllowStd<S>>),
 }
 
@@ -78,7 +291,7 @@ where
     AllowStd<S>: Read + Write,
 {
     fn drop(&mut self) {
-        (self.0).0.get_mut().context = null_mut();
+        self.0.inner.get_mut().context = null_mut();
     }
 }
 
@@ -147,9 +360,9 @@ impl<S> TlsStream<S> {
         F: FnOnce(&mut native_tls::TlsStream<AllowStd<S>>) -> R,
         AllowStd<S>: Read + Write,
     {
-        self.0.get_mut().context = ctx as *mut _ as *mut ();
+        self.inner.get_mut().context = ctx as *mut _ as *mut ();
         let g = Guard(self);
-        f(&mut (g.0).0)
+        f(&mut g.0.inner)
     }
 
     /// Returns a shared reference to the inner stream.
@@ -157,7 +370,7 @@ impl<S> TlsStream<S> {
     where
         S: AsyncRead + AsyncWrite + Unpin,
     {
-        &self.0.get_ref().inner
+        &self.inner.get_ref().inner
     }
 
     /// Returns a mutable reference to the inner stream.
@@ -165,7 +378,51 @@ impl<S> TlsStream<S> {
     where
         S: AsyncRead + AsyncWrite + Unpin,
     {
-        &mut self.0.get_mut().inner
+        &mut self.inner.get_mut().inner
+    }
+
+    /// Returns the server's SPIFFE ID, if it was verified by a connector
+    /// configured with `TlsConnectorBuilder::spiffe`.
+    pub fn spiffe_id(&self) -> Option<&str> {
+        self.spiffe_id.as_deref()
+    }
+
+    /// Returns the PROXY protocol header the connection started with, if it
+    /// was accepted by `TlsAcceptor::accept_proxied` and the peer sent one.
+    pub fn proxy_header(&self) -> Option<&ProxyHeader> {
+        self.proxy_header.as_ref()
+    }
+
+    /// Returns the certificate the peer presented during the handshake, if
+    /// any.
+    pub fn peer_certificate(&self) -> Result<Option<Certificate>, Error>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        self.inner.peer_certificate()
+    }
+
+    /// Returns the application protocol the server selected with ALPN, if
+    /// any was requested and negotiated.
+    pub fn negotiated_alpn(&self) -> Result<Option<Vec<u8>>, Error>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        self.inner.negotiated_alpn()
+    }
+}
+
+impl<S: fmt::Debug> fmt::Debug for TlsStream<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("TlsStream").field(&self.inner).finish()
+    }
+}
+
+impl<S> Drop for TlsStream<S> {
+    fn drop(&mut self) {
+        if !self.closed {
+            self.settings.observer.close();
+        }
     }
 }
 
@@ -182,7 +439,44 @@ where
         ctx: &mut Context<'_>,
         buf: &mut [u8],
     ) -> Poll<io::Result<usize>> {
-        self.with_context(ctx, |s| cvt(s.read(buf)))
+        // Drain anything left over from `poll_fill_buf` before reading more.
+        if self.read_pos < self.read_cap {
+            let n = cmp::min(buf.len(), self.read_cap - self.read_pos);
+            buf[..n].copy_from_slice(&self.read_buf[self.read_pos..self.read_pos + n]);
+            self.read_pos += n;
+            return Poll::Ready(Ok(n));
+        }
+
+        let r = self.with_context(ctx, |s| cvt(s.read(buf)));
+        if let Poll::Ready(Ok(n)) = r {
+            self.settings.observer.bytes_read(n);
+        }
+        r
+    }
+}
+
+impl<S> AsyncBufRead for TlsStream<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    fn poll_fill_buf(self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+        let this = self.get_mut();
+        if this.read_pos >= this.read_cap {
+            let mut buf = mem::take(&mut this.read_buf);
+            buf.resize(MAX_RECORD_PLAINTEXT, 0);
+            let r = this.with_context(ctx, |s| cvt(s.read(&mut buf)));
+            this.read_buf = buf;
+
+            let n = futures::ready!(r)?;
+            this.settings.observer.bytes_read(n);
+            this.read_pos = 0;
+            this.read_cap = n;
+        }
+        Poll::Ready(Ok(&this.read_buf[this.read_pos..this.read_cap]))
+    }
+
+    fn consume(mut self: Pin<&mut Self>, amt: usize) {
+        self.read_pos = cmp::min(self.read_pos + amt, self.read_cap);
     }
 }
 
@@ -195,7 +489,44 @@ where
         ctx: &mut Context<'_>,
         buf: &[u8],
     ) -> Poll<io::Result<usize>> {
-        self.with_context(ctx, |s| cvt(s.write(buf)))
+        let buf = &buf[..cmp::min(buf.len(), self.settings.max_send_fragment)];
+        let r = self.with_context(ctx, |s| cvt(s.write(buf)));
+        if let Poll::Ready(Ok(n)) = r {
+            self.settings.observer.bytes_written(n);
+        }
+        r
+    }
+
+    /// Writes as much of `bufs` as fits in one TLS record.
+    ///
+    /// Small slices, such as a frame header followed by its body, are copied
+    /// into a single record instead of each being sent as a record of its own.
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        ctx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        let max = self.settings.max_send_fragment;
+        match bufs.iter().find(|buf| !buf.is_empty()) {
+            None => return Poll::Ready(Ok(0)),
+            // Already a full record on its own, so copying gains nothing.
+            Some(buf) if buf.len() >= max => return self.poll_write(ctx, buf),
+            Some(_) => {}
+        }
+
+        let mut coalesced = mem::take(&mut self.write_buf);
+        coalesced.clear();
+        for buf in bufs {
+            let n = cmp::min(buf.len(), max - coalesced.len());
+            coalesced.extend_from_slice(&buf[..n]);
+            if coalesced.len() == max {
+                break;
+            }
+        }
+
+        let r = self.as_mut().poll_write(ctx, &coalesced);
+        self.write_buf = coalesced;
+        r
     }
 
     fn poll_flush(mut self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<io::Result<()>> {
@@ -204,14 +535,24 @@ where
 
     fn poll_close(mut self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<io::Result<()>> {
         match self.with_context(ctx, |s| s.shutdown()) {
-            Ok(()) => Poll::Ready(Ok(())),
+            Ok(()) => {
+                if !self.closed {
+                    self.closed = true;
+                    self.settings.observer.close();
+                }
+                Poll::Ready(Ok(()))
+            }
             Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => Poll::Pending,
             Err(e) => Poll::Ready(Err(e)),
         }
     }
 }
 
-async fn handshake<F, S>(f: F, stream: S) -> Result<TlsStream<S>, Error>
+async fn handshake<F, S>(
+    f: F,
+    stream: S,
+    settings: &StreamSettings,
+) -> Result<TlsStream<S>, Error>
 where
     F: FnOnce(
             AllowStd<S>,
@@ -219,12 +560,36 @@ where
         + Unpin,
     S: AsyncRead + AsyncWrite + Unpin,
 {
+    let observer = &settings.observer;
+    observer.handshake_start();
+    let started_at = Instant::now();
     let start = StartedHandshakeFuture(Some(StartedHandshakeFutureInner { f, stream }));
 
-    match start.await {
+    let result = match start.await {
         Err(e) => Err(e),
         Ok(StartedHandshake::Done(s)) => Ok(s),
         Ok(StartedHandshake::Mid(s)) => MidHandshake(Some(s)).await,
+    };
+
+    match result {
+        Ok(inner) => {
+            observer.handshake_success(started_at.elapsed());
+            Ok(TlsStream {
+                inner,
+                settings: settings.clone(),
+                closed: false,
+                write_buf: Vec::new(),
+                read_buf: Vec::new(),
+                read_pos: 0,
+                read_cap: 0,
+                spiffe_id: None,
+                proxy_header: None,
+            })
+        }
+        Err(e) => {
+            observer.handshake_failure(ErrorCategory::of(&e));
+            Err(e)
+        }
     }
 }
 
@@ -252,7 +617,7 @@ where
         match (inner.f)(stream) {
             Ok(mut s) => {
                 s.get_mut().context = null_mut();
-                Poll::Ready(Ok(StartedHandshake::Done(TlsStream(s))))
+                Poll::Ready(Ok(StartedHandshake::Done(s)))
             }
             Err(HandshakeError::WouldBlock(mut s)) => {
                 s.get_mut().context = null_mut();
@@ -265,13 +630,17 @@ where
 
 /// A builder for `TlsConnector`s.
 pub struct TlsConnectorBuilder {
-    inner: native_tls::TlsConnectorBuilder,
+    config: ConnectorConfig,
+    settings: StreamSettings,
+    checks: PeerChecks,
+    #[cfg(feature = "x509")]
+    spiffe_bundle: Option<Vec<Certificate>>,
 }
 
 impl TlsConnectorBuilder {
     /// Sets the identity to be used for client certificate authentication.
     pub fn identity(&mut self, identity: Identity) -> &mut TlsConnectorBuilder {
-        self.inner.identity(identity);
+        self.config.identity = Some(identity);
         self
     }
 
@@ -281,7 +650,7 @@ impl TlsConnectorBuilder {
     ///
     /// Defaults to `Some(Protocol::Tlsv10)`.
     pub fn min_protocol_version(&mut self, protocol: Option<Protocol>) -> &mut TlsConnectorBuilder {
-        self.inner.min_protocol_version(protocol);
+        self.config.min_protocol = protocol;
         self
     }
 
@@ -291,7 +660,7 @@ impl TlsConnectorBuilder {
     ///
     /// Defaults to `None`.
     pub fn max_protocol_version(&mut self, protocol: Option<Protocol>) -> &mut TlsConnectorBuilder {
-        self.inner.max_protocol_version(protocol);
+        self.config.max_protocol = protocol;
         self
     }
 
@@ -302,10 +671,33 @@ impl TlsConnectorBuilder {
     ///
     /// Defaults to an empty set.
     pub fn add_root_certificate(&mut self, cert: Certificate) -> &mut TlsConnectorBuilder {
-        self.inner.add_root_certificate(cert);
+        self.config.roots.push(cert);
         self
     }
 
+    /// Controls the use of the system's built-in trust roots.
+    ///
+    /// When disabled, only certificates added with `add_root_certificate` or
+    /// `trust_bundle` are trusted.
+    ///
+    /// Defaults to `false`.
+    pub fn disable_built_in_roots(&mut self, disable: bool) -> &mut TlsConnectorBuilder {
+        self.config.disable_built_in_roots = disable;
+        self
+    }
+
+    /// Trusts exactly the certificates in `pem`, a bundle of one or more
+    /// PEM-encoded certificates, instead of the system's built-in roots.
+    ///
+    /// Fails if any certificate in the bundle cannot be parsed. A bundle with
+    /// no certificates trusts nothing beyond roots added separately.
+    pub fn trust_bundle(&mut self, pem: &[u8]) -> Result<&mut TlsConnectorBuilder, Error> {
+        let certs = Certificate::stack_from_pem(pem)?;
+        self.config.disable_built_in_roots = true;
+        self.config.roots.extend(certs);
+        Ok(self)
+    }
+
     /// Controls the use of certificate validation.
     ///
     /// Defaults to `false`.
@@ -319,15 +711,27 @@ impl TlsConnectorBuilder {
         &mut self,
         accept_invalid_certs: bool,
     ) -> &mut TlsConnectorBuilder {
-        self.inner.danger_accept_invalid_certs(accept_invalid_certs);
+        self.config.accept_invalid_certs = accept_invalid_certs;
         self
     }
 
     /// Controls the use of Server Name Indication (SNI).
     ///
+    /// SNI is never sent when connecting to an IP address.
+    ///
     /// Defaults to `true`.
     pub fn use_sni(&mut self, use_sni: bool) -> &mut TlsConnectorBuilder {
-        self.inner.use_sni(use_sni);
+        self.config.use_sni = use_sni;
+        self
+    }
+
+    /// Requests the specified protocols through ALPN, in order of preference.
+    ///
+    /// The server's choice is available from `TlsStream::negotiated_alpn`.
+    ///
+    /// Defaults to no protocols.
+    pub fn request_alpns(&mut self, protocols: &[&str]) -> &mut TlsConnectorBuilder {
+        self.config.alpn = protocols.iter().map(|p| (*p).to_owned()).collect();
         self
     }
 
@@ -344,29 +748,113 @@ impl TlsConnectorBuilder {
         &mut self,
         accept_invalid_hostnames: bool,
     ) -> &mut TlsConnectorBuilder {
-        self.inner
-            .danger_accept_invalid_hostnames(accept_invalid_hostnames);
+        self.config.accept_invalid_hostnames = accept_invalid_hostnames;
+        self
+    }
+
+    /// Sets the observer notified of handshakes and traffic on connections
+    /// made by the connector.
+    ///
+    /// Defaults to `NoopObserver`.
+    pub fn observer(&mut self, observer: Arc<dyn Observer>) -> &mut TlsConnectorBuilder {
+        self.settings.observer = observer;
+        self
+    }
+
+    /// Sets the largest amount of plaintext sent in a single TLS record.
+    ///
+    /// Smaller records let the peer start decrypting sooner, which helps
+    /// latency-sensitive streams at the cost of some framing overhead. Values
+    /// above the protocol maximum of 16384 bytes have no effect.
+    ///
+    /// Defaults to 16384.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is zero.
+    pub fn max_send_fragment(&mut self, size: usize) -> &mut TlsConnectorBuilder {
+        assert!(size > 0, "max_send_fragment must be non-zero");
+        self.settings.max_send_fragment = cmp::min(size, MAX_RECORD_PLAINTEXT);
+        self
+    }
+
+    /// Refuses servers whose certificate is revoked by one of `lists`.
+    ///
+    /// Only the server's own certificate is checked, against the lists from
+    /// its issuer. The check runs after every handshake and fails with
+    /// `ConnectError::Revoked`, or with `ConnectError::RevocationUnknown` if
+    /// the issuer's only lists are past their next update. Keep a clone of
+    /// `lists` to replace them later without rebuilding the connector.
+    #[cfg(feature = "x509")]
+    pub fn revocation_lists(&mut self, lists: RevocationLists) -> &mut TlsConnectorBuilder {
+        self.checks.revocation = Some(lists);
+        self
+    }
+
+    /// Verifies servers by SPIFFE ID instead of by DNS name.
+    ///
+    /// `bundle` must be the trust bundle of the trust domain `expected` names,
+    /// and nothing else. SPIFFE scopes trust per trust domain, but `native-tls`
+    /// verifies against a single set of roots, so a root from another trust
+    /// domain could issue IDs in this one. A connector therefore verifies one
+    /// trust domain; use a connector per trust domain to reach several.
+    ///
+    /// `bundle` replaces every other root, including those added with
+    /// `add_root_certificate` and the built-in ones, whichever is called
+    /// first. Calling this again replaces the bundle. The domain passed when
+    /// connecting is still sent as SNI but is not checked against the
+    /// certificate; instead every connection requires the server's SPIFFE ID
+    /// to match `expected`, fails with `ConnectError::SpiffeMismatch`
+    /// otherwise, and records the verified ID in `TlsStream::spiffe_id`.
+    #[cfg(feature = "x509")]
+    pub fn spiffe(
+        &mut self,
+        bundle: &[Certificate],
+        expected: SpiffeMatch,
+    ) -> &mut TlsConnectorBuilder {
+        self.spiffe_bundle = Some(bundle.to_vec());
+        self.checks.spiffe = Some(expected);
         self
     }
 
     /// Creates a new `TlsConnector`.
     pub fn build(&self) -> Result<TlsConnector, Error> {
-        let connector = self.inner.build()?;
-        Ok(TlsConnector(connector))
+        let config = self.config.clone();
+        #[cfg(feature = "x509")]
+        let config = match &self.spiffe_bundle {
+            Some(bundle) => ConnectorConfig {
+                roots: bundle.clone(),
+                disable_built_in_roots: true,
+                accept_invalid_hostnames: true,
+                ..config
+            },
+            None => config,
+        };
+        let connector = config.build(config.use_sni)?;
+        Ok(TlsConnector {
+            inner: connector,
+            no_sni: Arc::default(),
+            config: Some(Arc::new(config)),
+            settings: self.settings.clone(),
+            checks: self.checks.clone(),
+        })
     }
 }
 
 impl TlsConnector {
     /// Returns a new connector with default settings.
     pub fn new() -> Result<TlsConnector, Error> {
-        let native_connector = native_tls::TlsConnector::new()?;
-        Ok(TlsConnector(native_connector))
+        TlsConnector::builder().build()
     }
 
     /// Returns a new builder for a `TlsConnector`.
     pub fn builder() -> TlsConnectorBuilder {
         TlsConnectorBuilder {
-            inner: native_tls::TlsConnector::builder(),
+            config: ConnectorConfig::default(),
+            settings: StreamSettings::default(),
+            checks: PeerChecks::default(),
+            #[cfg(feature = "x509")]
+            spiffe_bundle: None,
         }
     }
 
@@ -376,17 +864,91 @@ impl TlsConnector {
     /// This function will internally call `TlsConnector::connect` to connect
     /// the stream and returns a future representing the resolution of the
     /// connection operation. The returned future will resolve to either
-    /// `TlsStream<S>` or `Error` depending if it's successful or not.
+    /// `TlsStream<S>` or `ConnectError` depending if it's successful or not.
     ///
     /// This is typically used for clients who have already established, for
     /// example, a TCP connection to a remote server. That stream is then
     /// provided here to perform the client half of a connection to a
     /// TLS-powered server.
-    pub async fn connect<S>(&self, domain: &str, stream: S) -> Result<TlsStream<S>, Error>
+    ///
+    /// A `domain` that is an IP address is verified against the certificate's
+    /// IP address names and is not sent as SNI, unless this connector was
+    /// converted from a `native_tls::TlsConnector`. Other values are passed to
+    /// `native-tls` unchecked; use `connect_verified` to reject invalid names
+    /// before connecting.
+    ///
+    /// The checks configured on the builder that `native-tls` cannot make
+    /// during the handshake, such as `revocation_lists` and `spiffe`, are
+    /// applied once it completes. A server failing them is refused.
+    pub async fn connect<S>(&self, domain: &str, stream: S) -> Result<TlsStream<S>, ConnectError>
     where
         S: AsyncRead + AsyncWrite + Unpin,
     {
-        handshake(|s| self.0.connect(domain, s), stream).await
+        let inner = match (&self.config, domain.parse::<std::net::IpAddr>()) {
+            (Some(config), Ok(_)) if config.use_sni => self.no_sni(config)?,
+            _ => &self.inner,
+        };
+        let mut stream = self.connect_native(inner, domain, stream).await?;
+        self.checks.check(&mut stream)?;
+        Ok(stream)
+    }
+
+    fn no_sni(&self, config: &ConnectorConfig) -> Result<&native_tls::TlsConnector, Error> {
+        if let Some(no_sni) = self.no_sni.get() {
+            return Ok(no_sni);
+        }
+        let no_sni = config.build(false)?;
+        Ok(self.no_sni.get_or_init(|| no_sni))
+    }
+
+    async fn connect_native<S>(
+        &self,
+        inner: &native_tls::TlsConnector,
+        domain: &str,
+        stream: S,
+    ) -> Result<TlsStream<S>, Error>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let fut = handshake(|s| inner.connect(domain, s), stream, &self.settings);
+        #[cfg(feature = "tracing")]
+        let fut = trace::Handshake::new(fut, tracing::debug_span!("tls_connect", domain));
+        fut.await
+    }
+
+    /// Connects the provided stream like `connect`, but parses `domain` as a
+    /// `ServerName` first.
+    ///
+    /// An invalid name fails with `ConnectError::InvalidServerName` before
+    /// anything is sent. `TlsPool`, `connect_via` and `connect_host` connect
+    /// through this method.
+    pub async fn connect_verified<S>(
+        &self,
+        domain: &str,
+        stream: S,
+    ) -> Result<TlsStream<S>, ConnectError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let name = domain.parse::<ServerName>()?;
+        self.connect_to(&name, stream).await
+    }
+
+    /// Connects the provided stream to the server named by `name`, applying
+    /// the same checks as `connect`.
+    ///
+    /// A DNS name is sent as SNI, if enabled, and verified against the
+    /// certificate's DNS names. An IP address is verified against its IP
+    /// address names and never sent as SNI.
+    pub async fn connect_to<S>(
+        &self,
+        name: &ServerName,
+        stream: S,
+    ) -> Result<TlsStream<S>, ConnectError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        self.connect(&name.to_domain(), stream).await
     }
 }
 
@@ -396,15 +958,28 @@ impl fmt::Debug for TlsConnector {
     }
 }
 
+/// Wraps a connector built with `native-tls` directly.
+///
+/// The settings it was built with cannot be read back, so the result sends an
+/// IP address given to `connect` as SNI if the `native-tls` connector has SNI
+/// enabled, and `connect_with` cannot override its settings. Prefer
+/// `TlsConnector::builder` where possible.
 impl From<native_tls::TlsConnector> for TlsConnector {
     fn from(inner: native_tls::TlsConnector) -> TlsConnector {
-        TlsConnector(inner)
+        TlsConnector {
+            inner,
+            no_sni: Arc::default(),
+            config: None,
+            settings: StreamSettings::default(),
+            checks: PeerChecks::default(),
+        }
     }
 }
 
 /// A builder for `TlsAcceptor`s.
 pub struct TlsAcceptorBuilder {
     inner: native_tls::TlsAcceptorBuilder,
+    settings: StreamSettings,
 }
 
 impl TlsAcceptorBuilder {
@@ -428,10 +1003,39 @@ impl TlsAcceptorBuilder {
         self
     }
 
+    /// Sets the observer notified of handshakes and traffic on connections
+    /// accepted by the acceptor.
+    ///
+    /// Defaults to `NoopObserver`.
+    pub fn observer(&mut self, observer: Arc<dyn Observer>) -> &mut TlsAcceptorBuilder {
+        self.settings.observer = observer;
+        self
+    }
+
+    /// Sets the largest amount of plaintext sent in a single TLS record.
+    ///
+    /// Smaller records let the peer start decrypting sooner, which helps
+    /// latency-sensitive streams at the cost of some framing overhead. Values
+    /// above the protocol maximum of 16384 bytes have no effect.
+    ///
+    /// Defaults to 16384.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is zero.
+    pub fn max_send_fragment(&mut self, size: usize) -> &mut TlsAcceptorBuilder {
+        assert!(size > 0, "max_send_fragment must be non-zero");
+        self.settings.max_send_fragment = cmp::min(size, MAX_RECORD_PLAINTEXT);
+        self
+    }
+
     /// Creates a new `TlsAcceptor`.
     pub fn build(&self) -> Result<TlsAcceptor, Error> {
         let acceptor = self.inner.build()?;
-        Ok(TlsAcceptor(acceptor))
+        Ok(TlsAcceptor {
+            inner: acceptor,
+            settings: self.settings.clone(),
+        })
     }
 }
 
@@ -441,7 +1045,7 @@ impl TlsAcceptor {
     /// The identity acts as the server's private key/certificate chain.
     pub fn new(identity: Identity) -> Result<TlsAcceptor, Error> {
         let native_acceptor = native_tls::TlsAcceptor::new(identity)?;
-        Ok(TlsAcceptor(native_acceptor))
+        Ok(TlsAcceptor::from(native_acceptor))
     }
 
     /// Returns a new builder for a `TlsAcceptor`.
@@ -449,7 +1053,10 @@ impl TlsAcceptor {
     /// The identity acts as the server's private key/certificate chain.
     pub fn builder(identity: Identity) -> TlsAcceptorBuilder {
         let builder = native_tls::TlsAcceptor::builder(identity);
-        TlsAcceptorBuilder { inner: builder }
+        TlsAcceptorBuilder {
+            inner: builder,
+            settings: StreamSettings::default(),
+        }
     }
 
     /// Accepts a new client connection with the provided stream.
@@ -466,7 +1073,38 @@ impl TlsAcceptor {
     where
         S: AsyncRead + AsyncWrite + Unpin,
     {
-        handshake(|s| self.0.accept(s), stream).await
+        let fut = handshake(|s| self.inner.accept(s), stream, &self.settings);
+        #[cfg(feature = "tracing")]
+        let fut = trace::Handshake::new(fut, tracing::debug_span!("tls_accept"));
+        fut.await
+    }
+
+    /// Returns the certificate this acceptor presents to clients.
+    ///
+    /// `Identity` does not expose its contents, so this performs a handshake
+    /// with the acceptor over an in-memory stream and returns the certificate
+    /// it sent. That handshake is not reported to the acceptor's observer.
+    pub async fn certificate(&self) -> Result<Certificate, Error> {
+        let connector = native_tls::TlsConnector::builder()
+            .danger_accept_invalid_certs(true)
+            .danger_accept_invalid_hostnames(true)
+            .use_sni(false)
+            .build()?;
+        let connector = TlsConnector::from(connector);
+        let acceptor = TlsAcceptor {
+            inner: self.inner.clone(),
+            settings: StreamSettings::default(),
+        };
+        let (client, server) = testing::duplex(64 * 1024);
+        let (client, server) = futures::future::join(
+            connector.connect_native(&connector.inner, "localhost", client),
+            acceptor.accept(server),
+        )
+        .await;
+        let (client, _server) = (client?, server?);
+        Ok(client
+            .peer_certificate()?
+            .expect("an acceptor always sends a certificate"))
     }
 }
 
@@ -478,12 +1116,15 @@ impl fmt::Debug for TlsAcceptor {
 
 impl From<native_tls::TlsAcceptor> for TlsAcceptor {
     fn from(inner: native_tls::TlsAcceptor) -> TlsAcceptor {
-        TlsAcceptor(inner)
+        TlsAcceptor {
+            inner,
+            settings: StreamSettings::default(),
+        }
     }
 }
 
 impl<S: AsyncRead + AsyncWrite + Unpin> Future for MidHandshake<S> {
-    type Output = Result<TlsStream<S>, Error>;
+    type Output = Result<native_tls::TlsStream<AllowStd<S>>, Error>;
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         let mut_self = self.get_mut();
@@ -491,7 +1132,10 @@ impl<S: AsyncRead + AsyncWrite + Unpin> Future for MidHandshake<S> {
 
         s.get_mut().context = cx as *mut _ as *mut ();
         match s.handshake() {
-            Ok(stream) => Poll::Ready(Ok(TlsStream(stream))),
+            Ok(mut stream) => {
+                stream.get_mut().context = null_mut();
+                Poll::Ready(Ok(stream))
+            }
             Err(HandshakeError::Failure(e)) => Poll::Ready(Err(e)),
             Err(HandshakeError::WouldBlock(mut s)) => {
                 s.get_mut().context = null_mut();
@@ -0,0 +1,176 @@
+//! Hooks for instrumenting connections.
+//!
+//! An `Observer` attached to a `TlsConnector` or `TlsAcceptor` is told about
+//! every handshake made through it and about the traffic on each resulting
+//! `TlsStream`, which is enough to feed a metrics system without wrapping the
+//! stream type.
+
+use std::error::Error as StdError;
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime};
+
+use crate::Error;
+
+/// Receives events for the handshakes and streams of a connector or acceptor.
+///
+/// Every method has an empty default implementation, so implementors only
+/// need to override the events they care about. Methods are called inline
+/// from `poll` functions and should not block.
+pub trait Observer: Send + Sync {
+    /// Called when a handshake is started.
+    fn handshake_start(&self) {}
+
+    /// Called when a handshake completes, with the time it took.
+    fn handshake_success(&self, _duration: Duration) {}
+
+    /// Called when a handshake fails.
+    fn handshake_failure(&self, _category: ErrorCategory) {}
+
+    /// Called after `n` bytes of plaintext have been read from a stream.
+    fn bytes_read(&self, _n: usize) {}
+
+    /// Called after `n` bytes of plaintext have been written to a stream.
+    fn bytes_written(&self, _n: usize) {}
+
+    /// Called once when a stream is closed or dropped.
+    fn close(&self) {}
+
+    /// Called when an expiry check finds a certificate that expires within
+    /// the checked threshold or has already expired, with its subject and the
+    /// end of its validity period.
+    fn certificate_expiring(&self, _subject: &str, _not_after: SystemTime) {}
+}
+
+/// The broad cause of a failed handshake.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ErrorCategory {
+    /// The underlying transport failed, for example the peer reset the
+    /// connection.
+    Io,
+    /// The TLS layer rejected the handshake, for example because of a
+    /// certificate verification failure or a protocol mismatch.
+    Tls,
+}
+
+impl ErrorCategory {
+    /// Classifies a handshake error.
+    pub fn of(error: &Error) -> ErrorCategory {
+        let mut source = error.source();
+        while let Some(err) = source {
+            if err.is::<io::Error>() {
+                return ErrorCategory::Io;
+            }
+            source = err.source();
+        }
+        ErrorCategory::Tls
+    }
+}
+
+/// An `Observer` that ignores every event.
+///
+/// This is the observer used when none is configured.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoopObserver;
+
+impl Observer for NoopObserver {}
+
+/// An `Observer` that keeps running totals in atomic counters.
+#[derive(Debug, Default)]
+pub struct CountingObserver {
+    handshakes_started: AtomicU64,
+    handshakes_succeeded: AtomicU64,
+    handshake_io_failures: AtomicU64,
+    handshake_tls_failures: AtomicU64,
+    handshake_nanos: AtomicU64,
+    bytes_read: AtomicU64,
+    bytes_written: AtomicU64,
+    closed: AtomicU64,
+    certificates_expiring: AtomicU64,
+}
+
+impl CountingObserver {
+    /// Creates an observer with every counter at zero.
+    pub fn new() -> CountingObserver {
+        CountingObserver::default()
+    }
+
+    /// Returns the number of handshakes started.
+    pub fn handshakes_started(&self) -> u64 {
+        self.handshakes_started.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of handshakes that completed successfully.
+    pub fn handshakes_succeeded(&self) -> u64 {
+        self.handshakes_succeeded.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of handshakes that failed with the given category.
+    pub fn handshakes_failed(&self, category: ErrorCategory) -> u64 {
+        match category {
+            ErrorCategory::Io => self.handshake_io_failures.load(Ordering::Relaxed),
+            ErrorCategory::Tls => self.handshake_tls_failures.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Returns the total time spent in successful handshakes.
+    pub fn handshake_time(&self) -> Duration {
+        Duration::from_nanos(self.handshake_nanos.load(Ordering::Relaxed))
+    }
+
+    /// Returns the number of plaintext bytes read.
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes_read.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of plaintext bytes written.
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of streams closed or dropped.
+    pub fn closed(&self) -> u64 {
+        self.closed.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of expiry checks that found a certificate close to
+    /// expiring.
+    pub fn certificates_expiring(&self) -> u64 {
+        self.certificates_expiring.load(Ordering::Relaxed)
+    }
+}
+
+impl Observer for CountingObserver {
+    fn handshake_start(&self) {
+        self.handshakes_started.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn handshake_success(&self, duration: Duration) {
+        self.handshakes_succeeded.fetch_add(1, Ordering::Relaxed);
+        self.handshake_nanos
+            .fetch_add(duration.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    fn handshake_failure(&self, category: ErrorCategory) {
+        match category {
+            ErrorCategory::Io => self.handshake_io_failures.fetch_add(1, Ordering::Relaxed),
+            ErrorCategory::Tls => self.handshake_tls_failures.fetch_add(1, Ordering::Relaxed),
+        };
+    }
+
+    fn bytes_read(&self, n: usize) {
+        self.bytes_read.fetch_add(n as u64, Ordering::Relaxed);
+    }
+
+    fn bytes_written(&self, n: usize) {
+        self.bytes_written.fetch_add(n as u64, Ordering::Relaxed);
+    }
+
+    fn close(&self) {
+        self.closed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn certificate_expiring(&self, _subject: &str, _not_after: SystemTime) {
+        self.certificates_expiring.fetch_add(1, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,218 @@
+//! Throwaway certificate authorities for development and tests.
+//!
+//! A `CertificateAuthority` generates a self-signed root and issues leaf
+//! certificates for a set of names. Each `IssuedCertificate` carries an
+//! `Identity` ready for `TlsAcceptor::new` or `TlsConnectorBuilder::identity`,
+//! and the root `Certificate` is what peers add with
+//! `TlsConnectorBuilder::add_root_certificate` to trust it. The authority can
+//! also publish revocation lists for the certificates it issued.
+//!
+//! Keys are ECDSA P-256 and exist only in memory unless written out from the
+//! PEM accessors. Nothing here is meant for production PKI.
+
+use std::convert::TryInto;
+use std::fmt;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use rcgen::{
+    BasicConstraints, CertificateParams, CertificateRevocationListParams, DnType,
+    ExtendedKeyUsagePurpose, IsCa, KeyIdMethod, KeyPair, KeyUsagePurpose, RevokedCertParams,
+    SanType, SerialNumber,
+};
+use time::OffsetDateTime;
+
+use crate::{Certificate, GenerateError, Identity};
+
+/// How long a root generated by `CertificateAuthority::new` is valid for.
+const ROOT_VALIDITY: Duration = Duration::from_secs(10 * 365 * 24 * 60 * 60);
+
+/// How long a leaf issued by `CertificateAuthority::issue` is valid for.
+const LEAF_VALIDITY: Duration = Duration::from_secs(365 * 24 * 60 * 60);
+
+/// How far into the past validity periods start, to tolerate clock skew
+/// between the machines using a freshly issued certificate.
+const BACKDATE: Duration = Duration::from_secs(60 * 60);
+
+/// A self-signed root that issues leaf certificates.
+pub struct CertificateAuthority {
+    cert: rcgen::Certificate,
+    key: KeyPair,
+    next_serial: AtomicU64,
+}
+
+impl CertificateAuthority {
+    /// Generates a new root with the given common name, valid for ten years.
+    pub fn new(common_name: &str) -> Result<CertificateAuthority, GenerateError> {
+        let key = KeyPair::generate()?;
+        let mut params = CertificateParams::default();
+        params
+            .distinguished_name
+            .push(DnType::CommonName, common_name);
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        params.key_usages = vec![
+            KeyUsagePurpose::KeyCertSign,
+            KeyUsagePurpose::CrlSign,
+            KeyUsagePurpose::DigitalSignature,
+        ];
+        set_validity(&mut params, ROOT_VALIDITY);
+        let cert = params.self_signed(&key)?;
+        Ok(CertificateAuthority {
+            cert,
+            key,
+            next_serial: AtomicU64::new(1),
+        })
+    }
+
+    /// Returns the root certificate, for peers to trust.
+    pub fn root_certificate(&self) -> Certificate {
+        Certificate::from_der(self.cert.der()).expect("generated root is valid DER")
+    }
+
+    /// Returns the root certificate in PEM format.
+    pub fn root_certificate_pem(&self) -> String {
+        self.cert.pem()
+    }
+
+    /// Issues a leaf certificate for `names`, valid for one year.
+    ///
+    /// Each name becomes a subject alternative name: IP address literals as IP
+    /// addresses, names containing `://` as URIs and anything else as a DNS
+    /// name. The first name is also used as the common name. The certificate
+    /// may be used for both server and client authentication.
+    pub fn issue(&self, names: &[&str]) -> Result<IssuedCertificate, GenerateError> {
+        self.issue_valid_for(names, LEAF_VALIDITY)
+    }
+
+    /// Like `issue`, but the certificate expires after `validity` rather than
+    /// one year.
+    pub fn issue_valid_for(
+        &self,
+        names: &[&str],
+        validity: Duration,
+    ) -> Result<IssuedCertificate, GenerateError> {
+        let key = KeyPair::generate()?;
+        let mut params = CertificateParams::default();
+        if let Some(name) = names.first() {
+            params.distinguished_name.push(DnType::CommonName, *name);
+        }
+        for name in names {
+            params.subject_alt_names.push(san(name)?);
+        }
+        params.is_ca = IsCa::ExplicitNoCa;
+        params.key_usages = vec![
+            KeyUsagePurpose::DigitalSignature,
+            KeyUsagePurpose::KeyEncipherment,
+        ];
+        params.extended_key_usages = vec![
+            ExtendedKeyUsagePurpose::ServerAuth,
+            ExtendedKeyUsagePurpose::ClientAuth,
+        ];
+        params.use_authority_key_identifier_extension = true;
+        let serial = self.next_serial.fetch_add(1, Ordering::Relaxed);
+        params.serial_number = Some(SerialNumber::from(serial));
+        set_validity(&mut params, validity);
+        let cert = params.signed_by(&key, &self.cert, &self.key)?;
+
+        let cert_pem = cert.pem();
+        let key_pem = key.serialize_pem();
+        let identity = Identity::from_pkcs8(cert_pem.as_bytes(), key_pem.as_bytes())?;
+        Ok(IssuedCertificate {
+            identity,
+            serial,
+            cert_der: cert.der().to_vec(),
+            cert_pem,
+            key_pem,
+        })
+    }
+
+    /// Returns a revocation list in PEM format that revokes `revoked`, which
+    /// must have been issued by this authority.
+    ///
+    /// The list is valid from now for `validity`.
+    pub fn revocation_list_pem(
+        &self,
+        revoked: &[&IssuedCertificate],
+        validity: Duration,
+    ) -> Result<String, GenerateError> {
+        let now = OffsetDateTime::now_utc();
+        let params = CertificateRevocationListParams {
+            this_update: now - BACKDATE,
+            next_update: now + validity,
+            crl_number: SerialNumber::from(self.next_serial.fetch_add(1, Ordering::Relaxed)),
+            issuing_distribution_point: None,
+            revoked_certs: revoked
+                .iter()
+                .map(|cert| RevokedCertParams {
+                    serial_number: SerialNumber::from(cert.serial),
+                    revocation_time: now,
+                    reason_code: None,
+                    invalidity_date: None,
+                })
+                .collect(),
+            key_identifier_method: KeyIdMethod::Sha256,
+        };
+        Ok(params.signed_by(&self.cert, &self.key)?.pem()?)
+    }
+}
+
+impl fmt::Debug for CertificateAuthority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CertificateAuthority").finish()
+    }
+}
+
+/// A leaf certificate and private key issued by a `CertificateAuthority`.
+#[derive(Clone)]
+pub struct IssuedCertificate {
+    identity: Identity,
+    serial: u64,
+    cert_der: Vec<u8>,
+    cert_pem: String,
+    key_pem: String,
+}
+
+impl IssuedCertificate {
+    /// Returns the certificate and private key as an `Identity`.
+    pub fn identity(&self) -> Identity {
+        self.identity.clone()
+    }
+
+    /// Returns the leaf certificate.
+    pub fn certificate(&self) -> Certificate {
+        Certificate::from_der(&self.cert_der).expect("generated certificate is valid DER")
+    }
+
+    /// Returns the leaf certificate in PEM format.
+    pub fn certificate_pem(&self) -> &str {
+        &self.cert_pem
+    }
+
+    /// Returns the private key in PKCS #8 PEM format.
+    pub fn private_key_pem(&self) -> &str {
+        &self.key_pem
+    }
+}
+
+impl fmt::Debug for IssuedCertificate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IssuedCertificate").finish()
+    }
+}
+
+fn san(name: &str) -> Result<SanType, GenerateError> {
+    Ok(if let Ok(ip) = name.parse::<IpAddr>() {
+        SanType::IpAddress(ip)
+    } else if name.contains("://") {
+        SanType::URI(name.try_into()?)
+    } else {
+        SanType::DnsName(name.try_into()?)
+    })
+}
+
+fn set_validity(params: &mut CertificateParams, validity: Duration) {
+    let now = OffsetDateTime::now_utc();
+    params.not_before = now - BACKDATE;
+    params.not_after = now + validity;
+}
@@ -0,0 +1,240 @@
+//! Reuse of client connections.
+//!
+//! A `TlsPool` keeps idle `TlsStream`s keyed by the domain and port they were
+//! opened to, so callers can check a connection out, use it, and check it back
+//! in for the next request to the same server.
+//!
+//! The pool only limits how many connections it keeps idle. It does not cap
+//! the total number of connections to a host: every `checkout` that finds no
+//! reusable idle connection opens a new one, however many are already checked
+//! out. Callers that must bound concurrency per host need to do so themselves.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::ops::{Deref, DerefMut};
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use futures::io::{AsyncBufRead, AsyncRead, AsyncWrite};
+use futures::task::noop_waker;
+
+use crate::{ConnectError, TlsConnector, TlsStream};
+
+type Key = (String, u16);
+
+/// A builder for `TlsPool`s.
+pub struct TlsPoolBuilder {
+    connector: TlsConnector,
+    max_idle_time: Duration,
+    max_idle_per_host: usize,
+}
+
+impl TlsPoolBuilder {
+    /// Sets how long a connection may sit idle in the pool before it is
+    /// closed instead of being handed out.
+    ///
+    /// Defaults to 90 seconds.
+    pub fn max_idle_time(&mut self, max_idle_time: Duration) -> &mut TlsPoolBuilder {
+        self.max_idle_time = max_idle_time;
+        self
+    }
+
+    /// Sets the largest number of idle connections kept for a single domain
+    /// and port. Checking in a connection beyond this closes the oldest one.
+    ///
+    /// This does not limit connections that are checked out.
+    ///
+    /// Defaults to 8.
+    pub fn max_idle_per_host(&mut self, max_idle_per_host: usize) -> &mut TlsPoolBuilder {
+        self.max_idle_per_host = max_idle_per_host;
+        self
+    }
+
+    /// Creates a new `TlsPool` that opens transports with `connect`.
+    ///
+    /// `connect` is given the domain and port of each new connection and
+    /// returns the transport the TLS handshake is performed on, typically a
+    /// TCP stream.
+    pub fn build<S, C, F>(&self, connect: C) -> TlsPool<S, C>
+    where
+        C: Fn(&str, u16) -> F,
+        F: Future<Output = io::Result<S>>,
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        TlsPool {
+            connector: self.connector.clone(),
+            connect,
+            max_idle_time: self.max_idle_time,
+            max_idle_per_host: self.max_idle_per_host,
+            idle: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+/// A pool of idle client connections keyed by domain and port.
+pub struct TlsPool<S, C> {
+    connector: TlsConnector,
+    connect: C,
+    max_idle_time: Duration,
+    max_idle_per_host: usize,
+    idle: Mutex<HashMap<Key, Vec<Idle<S>>>>,
+}
+
+struct Idle<S> {
+    stream: TlsStream<S>,
+    since: Instant,
+}
+
+impl TlsPool<(), ()> {
+    /// Returns a new builder for a `TlsPool` that performs handshakes with
+    /// `connector`.
+    pub fn builder(connector: TlsConnector) -> TlsPoolBuilder {
+        TlsPoolBuilder {
+            connector,
+            max_idle_time: Duration::from_secs(90),
+            max_idle_per_host: 8,
+        }
+    }
+}
+
+impl<S, C, F> TlsPool<S, C>
+where
+    C: Fn(&str, u16) -> F,
+    F: Future<Output = io::Result<S>>,
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    /// Checks out a connection to `domain` on `port`.
+    ///
+    /// The most recently used idle connection is reused if it has not expired,
+    /// the peer has not closed it and it has no unread data. Otherwise a new
+    /// transport is opened and a handshake is performed for `domain`.
+    pub async fn checkout(&self, domain: &str, port: u16) -> Result<Pooled<S>, ConnectError> {
+        let key = (domain.to_owned(), port);
+        if let Some(stream) = self.take_idle(&key) {
+            return Ok(Pooled { key, stream });
+        }
+
+        let transport = (self.connect)(domain, port).await?;
+        let stream = self.connector.connect_verified(domain, transport).await?;
+        Ok(Pooled { key, stream })
+    }
+
+    /// Returns a connection to the pool so a later `checkout` can reuse it.
+    ///
+    /// A connection with unread data, whether left over from the previous
+    /// exchange or sent by the peer since, is closed instead, so the next user
+    /// never sees it. So is one the peer has closed.
+    pub fn checkin(&self, mut pooled: Pooled<S>) {
+        if self.max_idle_per_host == 0 || !is_reusable(&mut pooled.stream) {
+            return;
+        }
+        let mut idle = self.idle.lock().unwrap();
+        let conns = idle.entry(pooled.key).or_default();
+        if conns.len() >= self.max_idle_per_host {
+            conns.remove(0);
+        }
+        conns.push(Idle {
+            stream: pooled.stream,
+            since: Instant::now(),
+        });
+    }
+
+    /// Returns the number of idle connections held for `domain` on `port`.
+    pub fn idle_count(&self, domain: &str, port: u16) -> usize {
+        let idle = self.idle.lock().unwrap();
+        idle.get(&(domain.to_owned(), port)).map_or(0, Vec::len)
+    }
+
+    fn take_idle(&self, key: &Key) -> Option<TlsStream<S>> {
+        let mut idle = self.idle.lock().unwrap();
+        let conns = idle.get_mut(key)?;
+        let now = Instant::now();
+        conns.retain(|conn| now.duration_since(conn.since) < self.max_idle_time);
+
+        let mut found = None;
+        while let Some(mut conn) = conns.pop() {
+            if is_reusable(&mut conn.stream) {
+                found = Some(conn.stream);
+                break;
+            }
+        }
+        if conns.is_empty() {
+            idle.remove(key);
+        }
+        found
+    }
+}
+
+/// Checks, without waiting, that a stream is still open and has nothing left
+/// to read, so a new request on it only sees its own response.
+fn is_reusable<S>(stream: &mut TlsStream<S>) -> bool
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let waker = noop_waker();
+    let mut cx = Context::from_waker(&waker);
+    // Anything ready is unread data, end of file or an error.
+    matches!(Pin::new(stream).poll_fill_buf(&mut cx), Poll::Pending)
+}
+
+impl<S, C> fmt::Debug for TlsPool<S, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TlsPool")
+            .field("max_idle_time", &self.max_idle_time)
+            .field("max_idle_per_host", &self.max_idle_per_host)
+            .finish()
+    }
+}
+
+/// A connection checked out of a `TlsPool`.
+///
+/// Dereferences to the underlying `TlsStream`. Pass it back to
+/// `TlsPool::checkin` to make it available for reuse, or drop it to close it.
+pub struct Pooled<S> {
+    key: Key,
+    stream: TlsStream<S>,
+}
+
+impl<S> Pooled<S> {
+    /// Returns the domain this connection was opened to.
+    pub fn domain(&self) -> &str {
+        &self.key.0
+    }
+
+    /// Returns the port this connection was opened to.
+    pub fn port(&self) -> u16 {
+        self.key.1
+    }
+
+    /// Takes the stream out of the pool's control.
+    pub fn into_inner(self) -> TlsStream<S> {
+        self.stream
+    }
+}
+
+impl<S> Deref for Pooled<S> {
+    type Target = TlsStream<S>;
+
+    fn deref(&self) -> &TlsStream<S> {
+        &self.stream
+    }
+}
+
+impl<S> DerefMut for Pooled<S> {
+    fn deref_mut(&mut self) -> &mut TlsStream<S> {
+        &mut self.stream
+    }
+}
+
+impl<S: fmt::Debug> fmt::Debug for Pooled<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Pooled")
+            .field("key", &self.key)
+            .field("stream", &self.stream)
+            .finish()
+    }
+}
@@ -0,0 +1,316 @@
+//! Tunnelling through HTTP CONNECT and SOCKS5 proxies.
+//!
+//! `tunnel` asks a proxy to open a connection to a target host over an
+//! already connected transport. `TlsConnector::connect_via` does the same and
+//! then performs the TLS handshake with the target through the tunnel.
+
+use std::io;
+use std::net::IpAddr;
+
+use futures::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::{ConnectError, ServerName, TlsConnector, TlsStream};
+
+/// The longest HTTP CONNECT response header accepted from a proxy.
+const MAX_RESPONSE_HEADER: usize = 8 * 1024;
+
+/// A proxy to tunnel through.
+#[derive(Clone, Debug)]
+pub enum Proxy {
+    /// An HTTP proxy supporting the `CONNECT` method, with optional basic
+    /// authentication.
+    HttpConnect(Option<Credentials>),
+    /// A SOCKS5 proxy, with optional username/password authentication.
+    Socks5(Option<Credentials>),
+}
+
+/// A username and password for authenticating to a proxy.
+#[derive(Clone)]
+pub struct Credentials {
+    username: String,
+    password: String,
+}
+
+impl Credentials {
+    /// Creates credentials from a username and password.
+    pub fn new(username: &str, password: &str) -> Credentials {
+        Credentials {
+            username: username.to_owned(),
+            password: password.to_owned(),
+        }
+    }
+}
+
+impl std::fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Credentials")
+            .field("username", &self.username)
+            .finish()
+    }
+}
+
+/// Asks the proxy at the other end of `stream` to connect to `host` on
+/// `port`.
+///
+/// On success, everything subsequently written to and read from `stream` is
+/// relayed to and from the target. A proxy that refuses the credentials fails
+/// with `io::ErrorKind::PermissionDenied`.
+///
+/// `host` must be a valid DNS name or IP address. Anything else, such as a
+/// name containing line breaks, fails with `io::ErrorKind::InvalidInput`
+/// before anything is written to `stream`.
+pub async fn tunnel<S>(proxy: &Proxy, stream: &mut S, host: &str, port: u16) -> io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let host = match host.parse::<ServerName>() {
+        Ok(name) => name.to_domain(),
+        Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidInput, e)),
+    };
+    match proxy {
+        Proxy::HttpConnect(credentials) => http_connect(stream, &host, port, credentials).await,
+        Proxy::Socks5(credentials) => socks5_connect(stream, &host, port, credentials).await,
+    }
+}
+
+impl TlsConnector {
+    /// Opens a tunnel to `domain` on `port` through the proxy at the other end
+    /// of `stream`, then performs a handshake for `domain` through it.
+    ///
+    /// A `domain` that is neither a valid DNS name nor an IP address fails
+    /// with `ConnectError::InvalidServerName` before anything is sent to the
+    /// proxy.
+    pub async fn connect_via<S>(
+        &self,
+        proxy: &Proxy,
+        domain: &str,
+        port: u16,
+        mut stream: S,
+    ) -> Result<TlsStream<S>, ConnectError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        domain
+            .parse::<ServerName>()
+            .map_err(ConnectError::InvalidServerName)?;
+        tunnel(proxy, &mut stream, domain, port).await?;
+        self.connect_verified(domain, stream).await
+    }
+}
+
+async fn http_connect<S>(
+    stream: &mut S,
+    host: &str,
+    port: u16,
+    credentials: &Option<Credentials>,
+) -> io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let authority = match host.parse::<IpAddr>() {
+        Ok(IpAddr::V6(_)) => format!("[{}]:{}", host, port),
+        _ => format!("{}:{}", host, port),
+    };
+    let mut request = format!("CONNECT {0} HTTP/1.1\r\nHost: {0}\r\n", authority);
+    if let Some(credentials) = credentials {
+        let token = format!("{}:{}", credentials.username, credentials.password);
+        request.push_str("Proxy-Authorization: Basic ");
+        request.push_str(&base64(token.as_bytes()));
+        request.push_str("\r\n");
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes()).await?;
+    stream.flush().await?;
+
+    // Read one byte at a time so nothing after the header is consumed.
+    let mut header = Vec::new();
+    while !header.ends_with(b"\r\n\r\n") {
+        if header.len() == MAX_RESPONSE_HEADER {
+            return Err(invalid_data("proxy response header too long"));
+        }
+        let mut byte = [0u8];
+        stream.read_exact(&mut byte).await?;
+        header.push(byte[0]);
+    }
+
+    let status_line = header
+        .split(|&b| b == b'\n')
+        .next()
+        .map(|line| String::from_utf8_lossy(line).trim_end().to_owned())
+        .unwrap_or_default();
+    let mut parts = status_line.splitn(3, ' ');
+    let version = parts.next().unwrap_or("");
+    let status = parts.next().and_then(|s| s.parse::<u16>().ok());
+    if !version.starts_with("HTTP/1.") {
+        return Err(invalid_data("proxy did not respond with HTTP/1.x"));
+    }
+    match status {
+        Some(200..=299) => Ok(()),
+        Some(407) => Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("proxy authentication failed: {}", status_line),
+        )),
+        _ => Err(io::Error::other(format!(
+            "proxy refused to connect: {}",
+            status_line
+        ))),
+    }
+}
+
+async fn socks5_connect<S>(
+    stream: &mut S,
+    host: &str,
+    port: u16,
+    credentials: &Option<Credentials>,
+) -> io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    const VERSION: u8 = 5;
+    const NO_AUTH: u8 = 0x00;
+    const USER_PASS: u8 = 0x02;
+    const NO_ACCEPTABLE: u8 = 0xff;
+
+    let greeting: &[u8] = match credentials {
+        Some(_) => &[VERSION, 2, NO_AUTH, USER_PASS],
+        None => &[VERSION, 1, NO_AUTH],
+    };
+    stream.write_all(greeting).await?;
+    stream.flush().await?;
+
+    let mut reply = [0u8; 2];
+    stream.read_exact(&mut reply).await?;
+    if reply[0] != VERSION {
+        return Err(invalid_data("proxy is not a SOCKS5 server"));
+    }
+    match (reply[1], credentials) {
+        (NO_AUTH, _) => {}
+        (USER_PASS, Some(credentials)) => {
+            let username = credentials.username.as_bytes();
+            let password = credentials.password.as_bytes();
+            if username.len() > 255 || password.len() > 255 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "SOCKS5 username and password must be at most 255 bytes",
+                ));
+            }
+            let mut auth = vec![1, username.len() as u8];
+            auth.extend_from_slice(username);
+            auth.push(password.len() as u8);
+            auth.extend_from_slice(password);
+            stream.write_all(&auth).await?;
+            stream.flush().await?;
+
+            let mut status = [0u8; 2];
+            stream.read_exact(&mut status).await?;
+            if status[0] != 1 {
+                return Err(invalid_data(
+                    "SOCKS5 proxy sent an unknown authentication reply version",
+                ));
+            }
+            if status[1] != 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    "SOCKS5 proxy rejected the credentials",
+                ));
+            }
+        }
+        (NO_ACCEPTABLE, _) => {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "SOCKS5 proxy accepted none of the offered authentication methods",
+            ));
+        }
+        _ => return Err(invalid_data("SOCKS5 proxy chose an unoffered method")),
+    }
+
+    let mut request = vec![VERSION, 1, 0];
+    match host.parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) => {
+            request.push(1);
+            request.extend_from_slice(&ip.octets());
+        }
+        Ok(IpAddr::V6(ip)) => {
+            request.push(4);
+            request.extend_from_slice(&ip.octets());
+        }
+        Err(_) => {
+            if host.len() > 255 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "SOCKS5 host names must be at most 255 bytes",
+                ));
+            }
+            request.push(3);
+            request.push(host.len() as u8);
+            request.extend_from_slice(host.as_bytes());
+        }
+    }
+    request.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&request).await?;
+    stream.flush().await?;
+
+    let mut reply = [0u8; 4];
+    stream.read_exact(&mut reply).await?;
+    if reply[0] != VERSION {
+        return Err(invalid_data("proxy is not a SOCKS5 server"));
+    }
+    if reply[1] != 0 {
+        let reason = match reply[1] {
+            1 => "general failure",
+            2 => "connection not allowed by ruleset",
+            3 => "network unreachable",
+            4 => "host unreachable",
+            5 => "connection refused",
+            6 => "TTL expired",
+            7 => "command not supported",
+            8 => "address type not supported",
+            _ => "unknown error",
+        };
+        return Err(io::Error::other(format!(
+            "SOCKS5 proxy failed to connect: {}",
+            reason
+        )));
+    }
+
+    // Skip the bound address and port the proxy reports.
+    let addr_len = match reply[3] {
+        1 => 4,
+        4 => 16,
+        3 => {
+            let mut len = [0u8];
+            stream.read_exact(&mut len).await?;
+            len[0] as usize
+        }
+        _ => return Err(invalid_data("SOCKS5 proxy sent an unknown address type")),
+    };
+    let mut bound = vec![0u8; addr_len + 2];
+    stream.read_exact(&mut bound).await?;
+    Ok(())
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn base64(input: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut out = String::with_capacity(input.len().div_ceil(3) * 4);
+    for chunk in input.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).cloned().unwrap_or(0),
+            chunk.get(2).cloned().unwrap_or(0),
+        ];
+        let n = (u32::from(b[0]) << 16) | (u32::from(b[1]) << 8) | u32::from(b[2]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
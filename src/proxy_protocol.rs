@@ -0,0 +1,356 @@
+//! Accepting connections behind a load balancer that speaks the PROXY
+//! protocol.
+//!
+//! Load balancers that pass TCP through send a PROXY protocol header, in the
+//! text (v1) or binary (v2) format, before the client's first byte so the
+//! server learns the original addresses. `TlsAcceptor::accept_proxied` reads
+//! that header before the handshake. Since anyone can send one, it is only
+//! believed from peers that a `ProxyPolicy` trusts.
+
+use std::cmp;
+use std::convert::TryInto;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use futures::io::{AsyncRead, AsyncWrite};
+
+use crate::rewind::fill;
+use crate::{AcceptError, InvalidProxyHeader, Rewind, TlsAcceptor, TlsStream};
+
+/// The first bytes of a v2 header.
+const V2_SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+
+/// The longest v1 header, including the trailing CRLF.
+const V1_MAX_LEN: usize = 107;
+
+/// The fixed part of a v2 header: signature, version and command, family and
+/// length.
+const V2_FIXED_LEN: usize = 16;
+
+/// The contents of a PROXY protocol header.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProxyHeader {
+    source: Option<SocketAddr>,
+    destination: Option<SocketAddr>,
+    tlvs: Vec<(u8, Vec<u8>)>,
+}
+
+impl ProxyHeader {
+    /// Returns the address of the original client.
+    ///
+    /// `None` for health checks the proxy makes itself (v1 `UNKNOWN` and v2
+    /// `LOCAL`) and for address families other than TCP over IPv4 or IPv6.
+    pub fn source(&self) -> Option<SocketAddr> {
+        self.source
+    }
+
+    /// Returns the address the original client connected to, under the same
+    /// conditions as `source`.
+    pub fn destination(&self) -> Option<SocketAddr> {
+        self.destination
+    }
+
+    /// Returns the type and value of each TLV in a v2 header, in the order
+    /// they appear. Always empty for v1 headers.
+    pub fn tlvs(&self) -> &[(u8, Vec<u8>)] {
+        &self.tlvs
+    }
+
+    /// Returns the value of the first TLV of type `kind`, if any.
+    pub fn tlv(&self, kind: u8) -> Option<&[u8]> {
+        self.tlvs
+            .iter()
+            .find(|(k, _)| *k == kind)
+            .map(|(_, value)| &value[..])
+    }
+}
+
+/// Which peers may send a PROXY protocol header.
+///
+/// Peers are matched by the address of the transport connection, which is
+/// the load balancer's. A trusted peer must send a header unless
+/// `header_optional` is set. An untrusted peer must not send one; its
+/// connection is refused with `AcceptError::UntrustedProxy` rather than
+/// letting it claim another address.
+#[derive(Clone, Debug, Default)]
+pub struct ProxyPolicy {
+    trusted: Vec<(IpAddr, u8)>,
+    optional: bool,
+}
+
+impl ProxyPolicy {
+    /// Creates a policy that trusts no peers.
+    pub fn new() -> ProxyPolicy {
+        ProxyPolicy::default()
+    }
+
+    /// Trusts peers in the network `addr/prefix_len`, for example
+    /// `10.0.0.0/8`.
+    ///
+    /// IPv4 peers connecting over IPv6 as IPv4-mapped addresses match IPv4
+    /// networks.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `prefix_len` is longer than the address.
+    pub fn trust(&mut self, addr: IpAddr, prefix_len: u8) -> &mut ProxyPolicy {
+        let max = match addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        assert!(
+            prefix_len <= max,
+            "prefix length {} is too long",
+            prefix_len
+        );
+        self.trusted.push((mask(addr, prefix_len), prefix_len));
+        self
+    }
+
+    /// Lets trusted peers omit the header, for load balancers that only send
+    /// it on some listeners.
+    ///
+    /// Defaults to `false`.
+    pub fn header_optional(&mut self, optional: bool) -> &mut ProxyPolicy {
+        self.optional = optional;
+        self
+    }
+
+    /// Returns whether `peer` may send a header.
+    pub fn is_trusted(&self, peer: IpAddr) -> bool {
+        let peer = peer.to_canonical();
+        self.trusted
+            .iter()
+            .any(|&(network, prefix_len)| mask(peer, prefix_len) == network)
+    }
+}
+
+fn mask(addr: IpAddr, prefix_len: u8) -> IpAddr {
+    match addr {
+        IpAddr::V4(v4) => {
+            let bits = u32::from(v4)
+                .checked_shr(32 - u32::from(prefix_len))
+                .and_then(|b| b.checked_shl(32 - u32::from(prefix_len)))
+                .unwrap_or(0);
+            IpAddr::V4(Ipv4Addr::from(bits))
+        }
+        IpAddr::V6(v6) => {
+            let bits = u128::from(v6)
+                .checked_shr(128 - u32::from(prefix_len))
+                .and_then(|b| b.checked_shl(128 - u32::from(prefix_len)))
+                .unwrap_or(0);
+            IpAddr::V6(Ipv6Addr::from(bits))
+        }
+    }
+}
+
+impl TlsAcceptor {
+    /// Accepts a connection from `peer` that may start with a PROXY protocol
+    /// header, then performs the handshake.
+    ///
+    /// `peer` is the address of the transport's remote end, checked against
+    /// `policy`. The header, if one was read, is available from
+    /// `TlsStream::proxy_header`. Bytes read past the header are replayed to
+    /// the handshake through `Rewind`.
+    ///
+    /// Nothing here limits how long a peer may take to send its header; wrap
+    /// the call in a timeout as for `accept`.
+    pub async fn accept_proxied<S>(
+        &self,
+        policy: &ProxyPolicy,
+        peer: SocketAddr,
+        mut stream: S,
+    ) -> Result<TlsStream<Rewind<S>>, AcceptError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let mut buf = Vec::new();
+        let header = if policy.is_trusted(peer.ip()) {
+            match read_header(&mut stream, &mut buf).await? {
+                Some((header, len)) => {
+                    buf.drain(..len);
+                    Some(header)
+                }
+                None if policy.optional => None,
+                None => return Err(invalid("missing header")),
+            }
+        } else {
+            // Neither header format can start a TLS record, so one byte is
+            // enough to tell them apart.
+            if fill(&mut stream, &mut buf, 1).await.is_ok() && looks_like_header(&buf) {
+                return Err(AcceptError::UntrustedProxy(peer.ip()));
+            }
+            None
+        };
+
+        let mut stream = self.accept(Rewind::new(buf, stream)).await?;
+        stream.proxy_header = header;
+        Ok(stream)
+    }
+}
+
+fn invalid(reason: &'static str) -> AcceptError {
+    AcceptError::InvalidProxyHeader(InvalidProxyHeader { reason })
+}
+
+fn looks_like_header(buf: &[u8]) -> bool {
+    buf.first()
+        .is_some_and(|&b| b == b'P' || b == V2_SIGNATURE[0])
+}
+
+/// Reads a header into `buf`, returning it and its length, or `None` if the
+/// stream does not start with one.
+async fn read_header<S>(
+    stream: &mut S,
+    buf: &mut Vec<u8>,
+) -> Result<Option<(ProxyHeader, usize)>, AcceptError>
+where
+    S: AsyncRead + Unpin,
+{
+    fill(stream, buf, 1).await?;
+    if !looks_like_header(buf) {
+        return Ok(None);
+    }
+
+    if buf[0] == b'P' {
+        loop {
+            let searched = cmp::min(buf.len(), V1_MAX_LEN);
+            if let Some(end) = buf[..searched].windows(2).position(|w| w == b"\r\n") {
+                let header = parse_v1(&buf[..end])?;
+                return Ok(Some((header, end + 2)));
+            }
+            if searched == V1_MAX_LEN {
+                return Err(invalid("v1 header is too long"));
+            }
+            fill(stream, buf, buf.len() + 1).await?;
+        }
+    }
+
+    fill(stream, buf, V2_FIXED_LEN).await?;
+    if buf[..V2_SIGNATURE.len()] != V2_SIGNATURE {
+        return Err(invalid("bad v2 signature"));
+    }
+    let len = V2_FIXED_LEN + usize::from(u16::from_be_bytes([buf[14], buf[15]]));
+    fill(stream, buf, len).await?;
+    let header = parse_v2(buf[12], buf[13], &buf[V2_FIXED_LEN..len])?;
+    Ok(Some((header, len)))
+}
+
+fn parse_v1(line: &[u8]) -> Result<ProxyHeader, AcceptError> {
+    let line = std::str::from_utf8(line).map_err(|_| invalid("v1 header is not ASCII"))?;
+    let parts = line.split(' ').collect::<Vec<_>>();
+    if parts[0] != "PROXY" || parts.len() < 2 {
+        return Err(invalid("bad v1 signature"));
+    }
+    let v6 = match parts[1] {
+        "UNKNOWN" => {
+            return Ok(ProxyHeader {
+                source: None,
+                destination: None,
+                tlvs: Vec::new(),
+            })
+        }
+        "TCP4" => false,
+        "TCP6" => true,
+        _ => return Err(invalid("unknown v1 protocol")),
+    };
+    if parts.len() != 6 {
+        return Err(invalid("wrong number of v1 fields"));
+    }
+
+    let addr = |ip: &str, port: &str| -> Result<SocketAddr, AcceptError> {
+        let ip = if v6 {
+            ip.parse::<Ipv6Addr>().map(IpAddr::V6)
+        } else {
+            ip.parse::<Ipv4Addr>().map(IpAddr::V4)
+        }
+        .map_err(|_| invalid("bad v1 address"))?;
+        if port.is_empty() || !port.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(invalid("bad v1 port"));
+        }
+        let port = port.parse().map_err(|_| invalid("bad v1 port"))?;
+        Ok(SocketAddr::new(ip, port))
+    };
+    Ok(ProxyHeader {
+        source: Some(addr(parts[2], parts[4])?),
+        destination: Some(addr(parts[3], parts[5])?),
+        tlvs: Vec::new(),
+    })
+}
+
+fn parse_v2(version_command: u8, family: u8, body: &[u8]) -> Result<ProxyHeader, AcceptError> {
+    if version_command >> 4 != 2 {
+        return Err(invalid("unsupported version"));
+    }
+    let mut header = ProxyHeader {
+        source: None,
+        destination: None,
+        tlvs: Vec::new(),
+    };
+    match version_command & 0x0f {
+        // LOCAL: the proxy's own connection; the body is ignored.
+        0 => return Ok(header),
+        1 => {}
+        _ => return Err(invalid("unknown v2 command")),
+    }
+
+    let addresses_len = match family >> 4 {
+        0 => 0,
+        1 => 12,
+        2 => 36,
+        3 => 216,
+        _ => return Err(invalid("unknown v2 address family")),
+    };
+    // Only connections over a stream transport (TCP) carry usable addresses;
+    // the body is still skipped for UNSPEC and DGRAM.
+    let stream = match family & 0x0f {
+        0 | 2 => false,
+        1 => true,
+        _ => return Err(invalid("unknown v2 transport protocol")),
+    };
+    if body.len() < addresses_len {
+        return Err(invalid("v2 addresses are truncated"));
+    }
+    let (addresses, mut tlvs) = body.split_at(addresses_len);
+    match family >> 4 {
+        1 if stream => {
+            let ip =
+                |b: &[u8]| IpAddr::V4(Ipv4Addr::from(u32::from_be_bytes(b.try_into().unwrap())));
+            let port = |b: &[u8]| u16::from_be_bytes([b[0], b[1]]);
+            header.source = Some(SocketAddr::new(
+                ip(&addresses[0..4]),
+                port(&addresses[8..10]),
+            ));
+            header.destination = Some(SocketAddr::new(
+                ip(&addresses[4..8]),
+                port(&addresses[10..12]),
+            ));
+        }
+        2 if stream => {
+            let ip =
+                |b: &[u8]| IpAddr::V6(Ipv6Addr::from(u128::from_be_bytes(b.try_into().unwrap())));
+            let port = |b: &[u8]| u16::from_be_bytes([b[0], b[1]]);
+            header.source = Some(SocketAddr::new(
+                ip(&addresses[0..16]),
+                port(&addresses[32..34]),
+            ));
+            header.destination = Some(SocketAddr::new(
+                ip(&addresses[16..32]),
+                port(&addresses[34..36]),
+            ));
+        }
+        _ => {}
+    }
+
+    while !tlvs.is_empty() {
+        if tlvs.len() < 3 {
+            return Err(invalid("v2 TLV is truncated"));
+        }
+        let len = usize::from(u16::from_be_bytes([tlvs[1], tlvs[2]]));
+        if tlvs.len() < 3 + len {
+            return Err(invalid("v2 TLV is truncated"));
+        }
+        header.tlvs.push((tlvs[0], tlvs[3..3 + len].to_vec()));
+        tlvs = &tlvs[3 + len..];
+    }
+    Ok(header)
+}
@@ -0,0 +1,102 @@
+//! Typed names for the server a connector is verifying.
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use crate::InvalidServerName;
+
+/// The name a connector verifies the server's certificate against.
+///
+/// A DNS name is sent as SNI and matched against the certificate's DNS
+/// names. An IP address is never sent as SNI, as RFC 6066 forbids it, and is
+/// matched against the certificate's IP address names.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ServerName {
+    /// A DNS host name, without a trailing dot.
+    Dns(String),
+    /// An IPv4 or IPv6 address.
+    Ip(IpAddr),
+}
+
+impl ServerName {
+    /// Returns the name in the form `native-tls` expects.
+    pub(crate) fn to_domain(&self) -> String {
+        match self {
+            ServerName::Dns(name) => name.clone(),
+            ServerName::Ip(ip) => ip.to_string(),
+        }
+    }
+}
+
+impl FromStr for ServerName {
+    type Err = InvalidServerName;
+
+    /// Parses an IP address, optionally in brackets, or a DNS name.
+    ///
+    /// DNS names must be at most 253 bytes of labels made of ASCII letters,
+    /// digits, hyphens and underscores, each 1 to 63 bytes long and not
+    /// starting or ending with a hyphen. A single trailing dot is removed. A
+    /// name whose last label is all digits is rejected, since it can only be
+    /// a mistyped IPv4 address.
+    fn from_str(s: &str) -> Result<ServerName, InvalidServerName> {
+        let invalid = || InvalidServerName { name: s.to_owned() };
+
+        let unbracketed = s
+            .strip_prefix('[')
+            .and_then(|s| s.strip_suffix(']'))
+            .unwrap_or(s);
+        if let Ok(ip) = unbracketed.parse::<IpAddr>() {
+            return Ok(ServerName::Ip(ip));
+        }
+
+        let name = s.strip_suffix('.').unwrap_or(s);
+        if name.is_empty() || name.len() > 253 {
+            return Err(invalid());
+        }
+        for label in name.split('.') {
+            let valid = !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_');
+            if !valid {
+                return Err(invalid());
+            }
+        }
+        if name
+            .rsplit('.')
+            .next()
+            .is_some_and(|last| last.bytes().all(|b| b.is_ascii_digit()))
+        {
+            return Err(invalid());
+        }
+        Ok(ServerName::Dns(name.to_owned()))
+    }
+}
+
+impl TryFrom<&str> for ServerName {
+    type Error = InvalidServerName;
+
+    fn try_from(s: &str) -> Result<ServerName, InvalidServerName> {
+        s.parse()
+    }
+}
+
+impl From<IpAddr> for ServerName {
+    fn from(ip: IpAddr) -> ServerName {
+        ServerName::Ip(ip)
+    }
+}
+
+impl fmt::Display for ServerName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ServerName::Dns(name) => f.write_str(name),
+            ServerName::Ip(ip) => ip.fmt(f),
+        }
+    }
+}
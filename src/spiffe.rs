@@ -0,0 +1,78 @@
+//! Verifying servers by SPIFFE ID.
+//!
+//! In SPIFFE a workload's identity is the single `spiffe://` URI SAN of its
+//! X.509 SVID, and trust is scoped to the bundle of its trust domain rather
+//! than to public roots. `TlsConnectorBuilder::spiffe` configures a connector
+//! that way, and every connection it makes then checks the server's ID
+//! instead of a DNS name.
+//!
+//! A connector trusts the bundle of a single trust domain. `native-tls` has
+//! one set of roots per connector, so bundles of several trust domains cannot
+//! be kept apart; reaching several trust domains takes a connector for each.
+//!
+//! There is no acceptor mode. `native-tls` acceptors cannot request client
+//! certificates, so a server has no SVID to verify; terminate mutual TLS in a
+//! SPIFFE-aware proxy in front of the service instead.
+
+use crate::x509::{CertificateInfo, SubjectAltName};
+use crate::{ConnectError, SpiffeMismatch};
+
+/// The SPIFFE IDs a connector accepts from servers.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SpiffeMatch {
+    /// Exactly this ID, for example `spiffe://example.org/web`.
+    Id(String),
+    /// Any ID in this trust domain, for example `example.org`.
+    TrustDomain(String),
+}
+
+impl SpiffeMatch {
+    fn matches(&self, id: &str) -> bool {
+        match self {
+            SpiffeMatch::Id(expected) => id == expected,
+            SpiffeMatch::TrustDomain(domain) => {
+                trust_domain(id).is_some_and(|td| td.eq_ignore_ascii_case(domain))
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for SpiffeMatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SpiffeMatch::Id(id) => f.write_str(id),
+            SpiffeMatch::TrustDomain(domain) => write!(f, "any ID in trust domain {}", domain),
+        }
+    }
+}
+
+/// Returns the SPIFFE ID of a DER-encoded SVID if it matches `expected`.
+///
+/// A missing or unparseable certificate has no ID, so it never matches.
+pub(crate) fn verify(expected: &SpiffeMatch, der: Option<&[u8]>) -> Result<String, ConnectError> {
+    let info = der.and_then(|der| CertificateInfo::from_der(der).ok());
+    let mut ids = info
+        .iter()
+        .flat_map(|info| info.subject_alt_names())
+        .filter_map(|san| match san {
+            SubjectAltName::Uri(uri) if uri.starts_with("spiffe://") => Some(uri),
+            _ => None,
+        });
+    // An SVID carries exactly one SPIFFE ID.
+    let found = match (ids.next(), ids.next()) {
+        (Some(id), None) => Some(id.clone()),
+        _ => None,
+    };
+    match found {
+        Some(id) if expected.matches(&id) => Ok(id),
+        found => Err(ConnectError::SpiffeMismatch(SpiffeMismatch {
+            expected: expected.to_string(),
+            found,
+        })),
+    }
+}
+
+fn trust_domain(id: &str) -> Option<&str> {
+    let rest = id.strip_prefix("spiffe://")?;
+    Some(rest.split('/').next().unwrap_or(rest))
+}
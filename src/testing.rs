@@ -0,0 +1,179 @@
+//! Helpers for testing code that uses `TlsStream`s without real sockets.
+//!
+//! `duplex` creates a pair of connected in-memory streams, and
+//! `connected_pair` performs a handshake over one so a test gets a client and
+//! a server `TlsStream` talking to each other.
+//!
+//! This module is only public with the `testing` feature, which is meant to be
+//! enabled from `[dev-dependencies]`.
+
+use std::cmp;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+#[cfg(feature = "testing")]
+use futures::future;
+use futures::io::{AsyncRead, AsyncWrite};
+
+#[cfg(feature = "testing")]
+use crate::{ConnectError, TlsAcceptor, TlsConnector, TlsStream};
+
+/// Creates a pair of connected in-memory streams.
+///
+/// Bytes written to one end can be read from the other. Each direction
+/// buffers at most `capacity` bytes; writes beyond that wait for the other end
+/// to read. Closing or dropping one end makes reads on the other end return
+/// end of file once the buffer is drained, and makes writes to it fail with
+/// `io::ErrorKind::BrokenPipe`.
+///
+/// # Panics
+///
+/// Panics if `capacity` is zero.
+#[cfg_attr(not(feature = "testing"), allow(unreachable_pub))]
+pub fn duplex(capacity: usize) -> (DuplexStream, DuplexStream) {
+    assert!(capacity > 0, "capacity must be non-zero");
+    let a = Arc::new(Mutex::new(Pipe::new(capacity)));
+    let b = Arc::new(Mutex::new(Pipe::new(capacity)));
+    (
+        DuplexStream {
+            read: a.clone(),
+            write: b.clone(),
+        },
+        DuplexStream { read: b, write: a },
+    )
+}
+
+/// Performs a handshake between `connector` and `acceptor` over an in-memory
+/// `duplex` stream, returning the client and server ends.
+///
+/// The acceptor's certificate must be valid for `domain` and trusted by the
+/// connector.
+///
+/// The connector's checks apply as in `TlsConnector::connect`, and a failed
+/// accept is reported as `ConnectError::Tls`.
+#[cfg(feature = "testing")]
+pub async fn connected_pair(
+    connector: &TlsConnector,
+    acceptor: &TlsAcceptor,
+    domain: &str,
+) -> Result<(TlsStream<DuplexStream>, TlsStream<DuplexStream>), ConnectError> {
+    let (client, server) = duplex(64 * 1024);
+    let (client, server) =
+        future::join(connector.connect(domain, client), acceptor.accept(server)).await;
+    Ok((client?, server?))
+}
+
+/// One end of an in-memory stream created by `duplex`.
+#[cfg_attr(not(feature = "testing"), allow(unreachable_pub))]
+pub struct DuplexStream {
+    read: Arc<Mutex<Pipe>>,
+    write: Arc<Mutex<Pipe>>,
+}
+
+/// One direction of a `duplex` stream.
+struct Pipe {
+    buf: VecDeque<u8>,
+    capacity: usize,
+    closed: bool,
+    read_waker: Option<Waker>,
+    write_waker: Option<Waker>,
+}
+
+impl Pipe {
+    fn new(capacity: usize) -> Pipe {
+        Pipe {
+            buf: VecDeque::with_capacity(capacity),
+            capacity,
+            closed: false,
+            read_waker: None,
+            write_waker: None,
+        }
+    }
+
+    fn close(&mut self) {
+        self.closed = true;
+        if let Some(waker) = self.read_waker.take() {
+            waker.wake();
+        }
+        if let Some(waker) = self.write_waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl AsyncRead for DuplexStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let mut pipe = self.read.lock().unwrap();
+        if pipe.buf.is_empty() {
+            if pipe.closed {
+                return Poll::Ready(Ok(0));
+            }
+            pipe.read_waker = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+
+        let n = cmp::min(buf.len(), pipe.buf.len());
+        for (dst, src) in buf.iter_mut().zip(pipe.buf.drain(..n)) {
+            *dst = src;
+        }
+        if let Some(waker) = pipe.write_waker.take() {
+            waker.wake();
+        }
+        Poll::Ready(Ok(n))
+    }
+}
+
+impl AsyncWrite for DuplexStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let mut pipe = self.write.lock().unwrap();
+        if pipe.closed {
+            return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into()));
+        }
+        let space = pipe.capacity - pipe.buf.len();
+        if space == 0 {
+            pipe.write_waker = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+
+        let n = cmp::min(buf.len(), space);
+        pipe.buf.extend(&buf[..n]);
+        if let Some(waker) = pipe.read_waker.take() {
+            waker.wake();
+        }
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.write.lock().unwrap().close();
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Drop for DuplexStream {
+    fn drop(&mut self) {
+        self.write.lock().unwrap().close();
+        self.read.lock().unwrap().close();
+    }
+}
+
+impl fmt::Debug for DuplexStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DuplexStream").finish()
+    }
+}
@@ -0,0 +1,60 @@
+//! `tracing` instrumentation for handshakes.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use pin_project_lite::pin_project;
+use tracing::Span;
+
+use crate::Error;
+
+pin_project! {
+    /// Runs a handshake future inside `span`, recording every round trip that
+    /// would block and the outcome once the handshake finishes.
+    pub(crate) struct Handshake<F> {
+        #[pin]
+        inner: F,
+        span: Span,
+        polls: usize,
+    }
+}
+
+impl<F> Handshake<F> {
+    pub(crate) fn new(inner: F, span: Span) -> Handshake<F> {
+        Handshake {
+            inner,
+            span,
+            polls: 0,
+        }
+    }
+}
+
+impl<F, T> Future for Handshake<F>
+where
+    F: Future<Output = Result<T, Error>>,
+{
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+        let this = self.project();
+        let _enter = this.span.enter();
+
+        *this.polls += 1;
+        let polls = *this.polls;
+        match this.inner.poll(cx) {
+            Poll::Pending => {
+                tracing::trace!(polls, "handshake would block");
+                Poll::Pending
+            }
+            Poll::Ready(Ok(s)) => {
+                tracing::debug!(polls, "handshake complete");
+                Poll::Ready(Ok(s))
+            }
+            Poll::Ready(Err(e)) => {
+                tracing::debug!(polls, error = %e, "handshake failed");
+                Poll::Ready(Err(e))
+            }
+        }
+    }
+}
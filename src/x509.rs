@@ -0,0 +1,345 @@
+//! Inspecting the contents of certificates.
+//!
+//! `Certificate` only exposes its DER encoding. `CertificateInfo` parses it
+//! into the fields callers usually want to log or check, whether the
+//! certificate came from `TlsStream::peer_certificate` or was loaded locally.
+
+use std::fmt;
+use std::net::IpAddr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use sha2::{Digest, Sha256};
+use x509_parser::certificate::X509Certificate;
+use x509_parser::extensions::GeneralName;
+use x509_parser::oid_registry::{
+    OID_EC_P256, OID_KEY_TYPE_DSA, OID_KEY_TYPE_EC_PUBLIC_KEY, OID_NIST_EC_P384, OID_NIST_EC_P521,
+    OID_PKCS1_RSAENCRYPTION, OID_SIG_ED25519, OID_SIG_ED448,
+};
+use x509_parser::prelude::FromDer;
+use x509_parser::time::ASN1Time;
+
+use crate::{Certificate, ParseCertificateError, ServerName};
+
+/// The parsed contents of a certificate.
+#[derive(Clone, Debug)]
+pub struct CertificateInfo {
+    subject: String,
+    subject_common_name: Option<String>,
+    issuer: String,
+    subject_alt_names: Vec<SubjectAltName>,
+    not_before: SystemTime,
+    not_after: SystemTime,
+    serial: Vec<u8>,
+    fingerprint: [u8; 32],
+    key_type: KeyType,
+    key_bits: usize,
+    extended_key_usages: Vec<ExtendedKeyUsage>,
+}
+
+/// An entry in a certificate's subject alternative name extension.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SubjectAltName {
+    /// A DNS name.
+    Dns(String),
+    /// An IP address.
+    Ip(IpAddr),
+    /// A URI.
+    Uri(String),
+    /// An email address.
+    Email(String),
+}
+
+/// The algorithm of a certificate's public key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyType {
+    /// RSA.
+    Rsa,
+    /// An elliptic curve key for ECDSA.
+    Ec,
+    /// Ed25519.
+    Ed25519,
+    /// Ed448.
+    Ed448,
+    /// DSA.
+    Dsa,
+    /// Any other algorithm.
+    Other,
+}
+
+/// A purpose listed in a certificate's extended key usage extension.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ExtendedKeyUsage {
+    /// Any purpose.
+    Any,
+    /// TLS server authentication.
+    ServerAuth,
+    /// TLS client authentication.
+    ClientAuth,
+    /// Code signing.
+    CodeSigning,
+    /// Email protection.
+    EmailProtection,
+    /// Time stamping.
+    TimeStamping,
+    /// Signing OCSP responses.
+    OcspSigning,
+    /// Any other purpose, as a dotted OID.
+    Other(String),
+}
+
+impl CertificateInfo {
+    /// Parses `cert`.
+    pub fn parse(cert: &Certificate) -> Result<CertificateInfo, ParseCertificateError> {
+        let der = cert.to_der().map_err(ParseCertificateError::tls)?;
+        CertificateInfo::from_der(&der)
+    }
+
+    /// Parses a DER-encoded certificate.
+    pub fn from_der(der: &[u8]) -> Result<CertificateInfo, ParseCertificateError> {
+        let (_, cert) = X509Certificate::from_der(der).map_err(ParseCertificateError::x509)?;
+
+        let mut subject_alt_names = Vec::new();
+        if let Some(san) = cert
+            .subject_alternative_name()
+            .map_err(ParseCertificateError::x509)?
+        {
+            for name in &san.value.general_names {
+                match name {
+                    GeneralName::DNSName(name) => {
+                        subject_alt_names.push(SubjectAltName::Dns(name.to_string()))
+                    }
+                    GeneralName::URI(uri) => {
+                        subject_alt_names.push(SubjectAltName::Uri(uri.to_string()))
+                    }
+                    GeneralName::RFC822Name(email) => {
+                        subject_alt_names.push(SubjectAltName::Email(email.to_string()))
+                    }
+                    GeneralName::IPAddress(bytes) => {
+                        if let Some(ip) = ip_from_bytes(bytes) {
+                            subject_alt_names.push(SubjectAltName::Ip(ip));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let mut extended_key_usages = Vec::new();
+        if let Some(eku) = cert
+            .extended_key_usage()
+            .map_err(ParseCertificateError::x509)?
+        {
+            let eku = eku.value;
+            let known = [
+                (eku.any, ExtendedKeyUsage::Any),
+                (eku.server_auth, ExtendedKeyUsage::ServerAuth),
+                (eku.client_auth, ExtendedKeyUsage::ClientAuth),
+                (eku.code_signing, ExtendedKeyUsage::CodeSigning),
+                (eku.email_protection, ExtendedKeyUsage::EmailProtection),
+                (eku.time_stamping, ExtendedKeyUsage::TimeStamping),
+                (eku.ocsp_signing, ExtendedKeyUsage::OcspSigning),
+            ];
+            for (present, usage) in known.iter().cloned() {
+                if present {
+                    extended_key_usages.push(usage);
+                }
+            }
+            for oid in &eku.other {
+                extended_key_usages.push(ExtendedKeyUsage::Other(oid.to_id_string()));
+            }
+        }
+
+        let spki = cert.public_key();
+        let algorithm = &spki.algorithm.algorithm;
+        let key_type = if *algorithm == OID_PKCS1_RSAENCRYPTION {
+            KeyType::Rsa
+        } else if *algorithm == OID_KEY_TYPE_EC_PUBLIC_KEY {
+            KeyType::Ec
+        } else if *algorithm == OID_SIG_ED25519 {
+            KeyType::Ed25519
+        } else if *algorithm == OID_SIG_ED448 {
+            KeyType::Ed448
+        } else if *algorithm == OID_KEY_TYPE_DSA {
+            KeyType::Dsa
+        } else {
+            KeyType::Other
+        };
+        // The size of an EC point does not give the size of a P-521 key, so
+        // go by the named curve where there is one.
+        let curve = spki
+            .algorithm
+            .parameters
+            .as_ref()
+            .and_then(|params| params.as_oid().ok());
+        let key_bits = match curve {
+            Some(ref curve) if key_type == KeyType::Ec && *curve == OID_EC_P256 => 256,
+            Some(ref curve) if key_type == KeyType::Ec && *curve == OID_NIST_EC_P384 => 384,
+            Some(ref curve) if key_type == KeyType::Ec && *curve == OID_NIST_EC_P521 => 521,
+            _ => spki.parsed().map(|key| key.key_size()).unwrap_or(0),
+        };
+
+        let subject_common_name = cert
+            .subject()
+            .iter_common_name()
+            .next()
+            .and_then(|cn| cn.as_str().ok())
+            .map(str::to_owned);
+        let mut fingerprint = [0; 32];
+        fingerprint.copy_from_slice(&Sha256::digest(der));
+
+        Ok(CertificateInfo {
+            subject: cert.subject().to_string(),
+            subject_common_name,
+            issuer: cert.issuer().to_string(),
+            subject_alt_names,
+            not_before: system_time(cert.validity().not_before),
+            not_after: system_time(cert.validity().not_after),
+            serial: cert.raw_serial().to_vec(),
+            fingerprint,
+            key_type,
+            key_bits,
+            extended_key_usages,
+        })
+    }
+
+    /// Returns the subject distinguished name, formatted as in RFC 4514, for
+    /// example `CN=example.com, O=Example`.
+    pub fn subject(&self) -> &str {
+        &self.subject
+    }
+
+    /// Returns the first common name in the subject, if any.
+    pub fn subject_common_name(&self) -> Option<&str> {
+        self.subject_common_name.as_deref()
+    }
+
+    /// Returns the issuer distinguished name, formatted like `subject`.
+    pub fn issuer(&self) -> &str {
+        &self.issuer
+    }
+
+    /// Returns the DNS, IP, URI and email entries of the subject alternative
+    /// name extension, in the order they appear.
+    pub fn subject_alt_names(&self) -> &[SubjectAltName] {
+        &self.subject_alt_names
+    }
+
+    /// Returns the start of the validity period.
+    pub fn not_before(&self) -> SystemTime {
+        self.not_before
+    }
+
+    /// Returns the end of the validity period.
+    pub fn not_after(&self) -> SystemTime {
+        self.not_after
+    }
+
+    /// Returns whether `time` falls within the validity period.
+    pub fn is_valid_at(&self, time: SystemTime) -> bool {
+        self.not_before <= time && time <= self.not_after
+    }
+
+    /// Returns the serial number as big-endian bytes.
+    pub fn serial(&self) -> &[u8] {
+        &self.serial
+    }
+
+    /// Returns the serial number as colon-separated hex.
+    pub fn serial_hex(&self) -> String {
+        hex(&self.serial)
+    }
+
+    /// Returns the SHA-256 digest of the DER encoding.
+    pub fn fingerprint_sha256(&self) -> [u8; 32] {
+        self.fingerprint
+    }
+
+    /// Returns the SHA-256 fingerprint as colon-separated hex.
+    pub fn fingerprint_sha256_hex(&self) -> String {
+        hex(&self.fingerprint)
+    }
+
+    /// Returns the algorithm of the public key.
+    pub fn key_type(&self) -> KeyType {
+        self.key_type
+    }
+
+    /// Returns the size of the public key in bits, or 0 if it is not known.
+    pub fn key_bits(&self) -> usize {
+        self.key_bits
+    }
+
+    /// Returns the purposes in the extended key usage extension. Empty if the
+    /// certificate has no such extension.
+    pub fn extended_key_usages(&self) -> &[ExtendedKeyUsage] {
+        &self.extended_key_usages
+    }
+
+    /// Returns whether the subject alternative names cover `name`.
+    ///
+    /// DNS names compare case-insensitively, and a wildcard matches exactly
+    /// one leftmost label. IP addresses must match an IP address entry. The
+    /// subject common name is not consulted.
+    pub fn is_valid_for(&self, name: &ServerName) -> bool {
+        self.subject_alt_names.iter().any(|san| match (san, name) {
+            (SubjectAltName::Dns(pattern), ServerName::Dns(name)) => dns_matches(pattern, name),
+            (SubjectAltName::Ip(ip), ServerName::Ip(name)) => ip == name,
+            _ => false,
+        })
+    }
+}
+
+fn dns_matches(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.strip_suffix('.').unwrap_or(pattern);
+    match pattern.strip_prefix("*.") {
+        Some(suffix) => match name.split_once('.') {
+            Some((label, rest)) => !label.is_empty() && rest.eq_ignore_ascii_case(suffix),
+            None => false,
+        },
+        None => pattern.eq_ignore_ascii_case(name),
+    }
+}
+
+impl fmt::Display for SubjectAltName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SubjectAltName::Dns(name) => write!(f, "DNS:{}", name),
+            SubjectAltName::Ip(ip) => write!(f, "IP:{}", ip),
+            SubjectAltName::Uri(uri) => write!(f, "URI:{}", uri),
+            SubjectAltName::Email(email) => write!(f, "email:{}", email),
+        }
+    }
+}
+
+fn ip_from_bytes(bytes: &[u8]) -> Option<IpAddr> {
+    match bytes.len() {
+        4 => {
+            let mut octets = [0; 4];
+            octets.copy_from_slice(bytes);
+            Some(IpAddr::from(octets))
+        }
+        16 => {
+            let mut octets = [0; 16];
+            octets.copy_from_slice(bytes);
+            Some(IpAddr::from(octets))
+        }
+        _ => None,
+    }
+}
+
+pub(crate) fn system_time(time: ASN1Time) -> SystemTime {
+    let secs = time.timestamp();
+    if secs >= 0 {
+        UNIX_EPOCH + Duration::from_secs(secs as u64)
+    } else {
+        UNIX_EPOCH - Duration::from_secs(secs.unsigned_abs())
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<Vec<_>>()
+        .join(":")
+}
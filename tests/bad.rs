@@ -3,7 +3,7 @@ use std::net::ToSocketAddrs;
 use cfg_if::cfg_if;
 use futures::FutureExt;
 use futures_tokio_compat::Compat;
-use tls_async::{Error, TlsConnector};
+use tls_async::{ConnectError, Error, TlsConnector};
 use tokio::net::TcpStream;
 
 fn check_cause(err: Error, s: &str) {
@@ -15,6 +15,13 @@ fn check_cause(err: Error, s: &str) {
     );
 }
 
+fn tls_error(err: ConnectError) -> Error {
+    match err {
+        ConnectError::Tls(e) => e,
+        e => panic!("expected a TLS error, got {:?}", e),
+    }
+}
+
 macro_rules! t {
     ($e:expr) => {
         match $e {
@@ -91,7 +98,7 @@ async fn get_host(host: String) -> Result<(), Error> {
     let socket = Compat::new(t!(TcpStream::connect(&addr).await));
     let builder = TlsConnector::builder();
     let cx = t!(builder.build());
-    cx.connect(&host, socket).await?;
+    cx.connect(&host, socket).await.map_err(tls_error)?;
     Ok(())
 }
 
@@ -0,0 +1,152 @@
+#![cfg(feature = "pki")]
+
+use futures::executor::block_on;
+use futures::future;
+use futures::io::{AsyncReadExt, AsyncWriteExt};
+use tls_async::testing::duplex;
+use tls_async::{
+    AcceptError, CertificateAuthority, ConnectOptions, StartHandshake, TlsAcceptor, TlsConnector,
+};
+
+macro_rules! t {
+    ($e:expr) => {
+        match $e {
+            Ok(e) => e,
+            Err(e) => panic!("{} failed with {:?}", stringify!($e), e),
+        }
+    };
+}
+
+/// Builds a ClientHello for `name` offering TLS 1.2 and 1.3, with GREASE
+/// values mixed in, split across two records.
+fn fragmented_hello(name: &str) -> Vec<u8> {
+    let mut sni = Vec::new();
+    sni.extend_from_slice(&((name.len() + 3) as u16).to_be_bytes());
+    sni.push(0);
+    sni.extend_from_slice(&(name.len() as u16).to_be_bytes());
+    sni.extend_from_slice(name.as_bytes());
+    let alpn = b"\x00\x0c\x02h2\x08http/1.1";
+    let versions = b"\x06\x1a\x1a\x03\x04\x03\x03";
+
+    let mut extensions = Vec::new();
+    for (kind, data) in &[(0u16, &sni[..]), (16, &alpn[..]), (43, &versions[..])] {
+        extensions.extend_from_slice(&kind.to_be_bytes());
+        extensions.extend_from_slice(&(data.len() as u16).to_be_bytes());
+        extensions.extend_from_slice(data);
+    }
+
+    let mut body = vec![0x03, 0x03];
+    body.extend_from_slice(&[7; 32]);
+    body.push(0);
+    body.extend_from_slice(b"\x00\x06\x0a\x0a\x13\x01\xc0\x2f");
+    body.extend_from_slice(b"\x01\x00");
+    body.extend_from_slice(&(extensions.len() as u16).to_be_bytes());
+    body.extend_from_slice(&extensions);
+
+    let mut message = vec![1, 0];
+    message.extend_from_slice(&(body.len() as u16).to_be_bytes());
+    message.extend_from_slice(&body);
+
+    let mut records = Vec::new();
+    let (first, second) = message.split_at(10);
+    for fragment in &[first, second] {
+        records.extend_from_slice(&[22, 3, 1]);
+        records.extend_from_slice(&(fragment.len() as u16).to_be_bytes());
+        records.extend_from_slice(fragment);
+    }
+    records
+}
+
+#[test]
+fn routes_by_server_name() {
+    let ca = t!(CertificateAuthority::new("tls-async test root"));
+    let a = t!(TlsAcceptor::new(t!(ca.issue(&["a.test"])).identity()));
+    let b = t!(TlsAcceptor::new(t!(ca.issue(&["b.test"])).identity()));
+    let connector = t!(TlsConnector::builder()
+        .add_root_certificate(ca.root_certificate())
+        .build());
+
+    let (client, server) = duplex(64 * 1024);
+    block_on(async {
+        let client = async {
+            let options = ConnectOptions::new(t!("b.test".parse()))
+                .alpn(&["h2", "http/1.1"])
+                .clone();
+            let mut stream = t!(connector.connect_with(&options, client).await);
+            t!(stream.write_all(b"ping").await);
+            t!(stream.flush().await);
+        };
+        let server = async {
+            let start = t!(StartHandshake::read(server).await);
+            let hello = start.client_hello();
+            assert_eq!(hello.server_name(), Some("b.test"));
+            assert_eq!(
+                hello.alpn_protocols(),
+                &[b"h2".to_vec(), b"http/1.1".to_vec()][..]
+            );
+            assert!(hello.versions().contains(&0x0303));
+            assert!(!hello.cipher_suites().is_empty());
+
+            let acceptor = match hello.server_name() {
+                Some("a.test") => &a,
+                _ => &b,
+            };
+            let mut stream = t!(start.accept(acceptor).await);
+            let mut buf = [0; 4];
+            t!(stream.read_exact(&mut buf).await);
+            assert_eq!(&buf, b"ping");
+        };
+        future::join(client, server).await;
+    });
+}
+
+#[test]
+fn parses_fragmented_hello() {
+    let bytes = fragmented_hello("example.com");
+    let (mut client, server) = duplex(64 * 1024);
+    block_on(async {
+        t!(client.write_all(&bytes).await);
+        drop(client);
+
+        let start = t!(StartHandshake::read(server).await);
+        let hello = start.client_hello();
+        assert_eq!(hello.server_name(), Some("example.com"));
+        assert_eq!(
+            hello.alpn_protocols(),
+            &[b"h2".to_vec(), b"http/1.1".to_vec()][..]
+        );
+        assert_eq!(hello.versions(), &[0x0304, 0x0303][..]);
+        assert_eq!(hello.cipher_suites(), &[0x1301, 0xc02f][..]);
+
+        let mut replayed = Vec::new();
+        t!(start.into_inner().read_to_end(&mut replayed).await);
+        assert_eq!(replayed, bytes);
+    });
+}
+
+#[test]
+fn rejects_other_protocols() {
+    let (mut client, server) = duplex(64 * 1024);
+    block_on(async {
+        t!(client.write_all(b"GET / HTTP/1.1\r\n\r\n").await);
+        match StartHandshake::read(server).await {
+            Err(AcceptError::InvalidClientHello(_)) => {}
+            other => panic!("expected an invalid ClientHello, got {:?}", other),
+        }
+    });
+}
+
+#[test]
+fn rejects_truncated_hello() {
+    let mut bytes = fragmented_hello("example.com");
+    bytes.truncate(bytes.len() - 1);
+    let (mut client, server) = duplex(64 * 1024);
+    block_on(async {
+        t!(client.write_all(&bytes).await);
+        drop(client);
+        match StartHandshake::read(server).await {
+            Err(AcceptError::Io(ref e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => {}
+            other => panic!("expected end of file, got {:?}", other),
+        }
+    });
+}
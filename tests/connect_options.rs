@@ -0,0 +1,197 @@
+#![cfg(feature = "pki")]
+
+use futures::executor::block_on;
+use futures::future;
+use tls_async::testing::{duplex, DuplexStream};
+use tls_async::{
+    CertificateAuthority, ConnectError, ConnectOptions, ServerName, TlsAcceptor, TlsConnector,
+    TlsStream,
+};
+
+macro_rules! t {
+    ($e:expr) => {
+        match $e {
+            Ok(e) => e,
+            Err(e) => panic!("{} failed with {:?}", stringify!($e), e),
+        }
+    };
+}
+
+fn connect(
+    connector: &TlsConnector,
+    acceptor: &TlsAcceptor,
+    options: &ConnectOptions,
+) -> Result<TlsStream<DuplexStream>, ConnectError> {
+    let (client, server) = duplex(64 * 1024);
+    block_on(async {
+        let (client, _server) = future::join(
+            connector.connect_with(options, client),
+            acceptor.accept(server),
+        )
+        .await;
+        client
+    })
+}
+
+fn name(s: &str) -> ServerName {
+    t!(s.parse())
+}
+
+#[test]
+fn alpn_is_requested_per_call() {
+    let ca = t!(CertificateAuthority::new("tls-async test root"));
+    let leaf = t!(ca.issue(&["localhost"]));
+    let acceptor = TlsAcceptor::from(t!(native_tls::TlsAcceptor::builder(leaf.identity())
+        .accept_alpn(&["http/1.1"])
+        .build()));
+    let connector = t!(TlsConnector::builder()
+        .add_root_certificate(ca.root_certificate())
+        .build());
+
+    let stream = t!(connect(
+        &connector,
+        &acceptor,
+        ConnectOptions::new(name("localhost")).alpn(&["h2", "http/1.1"]),
+    ));
+    assert_eq!(t!(stream.negotiated_alpn()), Some(b"http/1.1".to_vec()));
+
+    let stream = t!(connect(
+        &connector,
+        &acceptor,
+        &ConnectOptions::new(name("localhost"))
+    ));
+    assert_eq!(t!(stream.negotiated_alpn()), None);
+}
+
+#[test]
+fn alpn_overrides_the_connector() {
+    let ca = t!(CertificateAuthority::new("tls-async test root"));
+    let leaf = t!(ca.issue(&["localhost"]));
+    let acceptor = TlsAcceptor::from(t!(native_tls::TlsAcceptor::builder(leaf.identity())
+        .accept_alpn(&["h2", "http/1.1"])
+        .build()));
+    let connector = t!(TlsConnector::builder()
+        .add_root_certificate(ca.root_certificate())
+        .request_alpns(&["h2"])
+        .build());
+
+    let stream = t!(connect(
+        &connector,
+        &acceptor,
+        &ConnectOptions::new(name("localhost"))
+    ));
+    assert_eq!(t!(stream.negotiated_alpn()), Some(b"h2".to_vec()));
+
+    let stream = t!(connect(
+        &connector,
+        &acceptor,
+        ConnectOptions::new(name("localhost")).alpn(&["http/1.1"]),
+    ));
+    assert_eq!(t!(stream.negotiated_alpn()), Some(b"http/1.1".to_vec()));
+}
+
+#[test]
+fn sni_can_be_disabled() {
+    let ca = t!(CertificateAuthority::new("tls-async test root"));
+    let leaf = t!(ca.issue(&["localhost"]));
+    let acceptor = t!(TlsAcceptor::new(leaf.identity()));
+    let connector = t!(TlsConnector::builder()
+        .add_root_certificate(ca.root_certificate())
+        .build());
+
+    t!(connect(
+        &connector,
+        &acceptor,
+        ConnectOptions::new(name("localhost")).disable_sni(),
+    ));
+    assert!(connect(
+        &connector,
+        &acceptor,
+        ConnectOptions::new(name("example.com")).disable_sni(),
+    )
+    .is_err());
+}
+
+#[test]
+fn native_connector_cannot_be_overridden() {
+    let connector = TlsConnector::from(t!(native_tls::TlsConnector::new()));
+    let acceptor = {
+        let ca = t!(CertificateAuthority::new("tls-async test root"));
+        t!(TlsAcceptor::new(t!(ca.issue(&["localhost"])).identity()))
+    };
+    match connect(
+        &connector,
+        &acceptor,
+        ConnectOptions::new(name("localhost")).alpn(&["h2"]),
+    ) {
+        Err(ConnectError::OptionsUnsupported) => {}
+        other => panic!("expected an unsupported error, got {:?}", other),
+    }
+}
+
+#[test]
+fn default_connector_can_be_overridden() {
+    let ca = t!(CertificateAuthority::new("tls-async test root"));
+    let leaf = t!(ca.issue(&["localhost"]));
+    let acceptor = t!(TlsAcceptor::new(leaf.identity()));
+    let connector = t!(TlsConnector::new());
+
+    // The test root is not trusted, so the handshake fails, but only after
+    // the options were applied.
+    match connect(
+        &connector,
+        &acceptor,
+        ConnectOptions::new(name("localhost")).alpn(&["h2"]),
+    ) {
+        Err(ConnectError::Tls(_)) => {}
+        other => panic!("expected a handshake failure, got {:?}", other),
+    }
+}
+
+#[cfg(feature = "x509")]
+#[test]
+fn sni_is_sent_separately_from_verification_name() {
+    let ca = t!(CertificateAuthority::new("tls-async test root"));
+    let leaf = t!(ca.issue(&["backend.internal"]));
+    let acceptor = t!(TlsAcceptor::new(leaf.identity()));
+    let connector = t!(TlsConnector::builder()
+        .add_root_certificate(ca.root_certificate())
+        .build());
+
+    t!(connect(
+        &connector,
+        &acceptor,
+        ConnectOptions::new(name("backend.internal")).sni("front.example.com"),
+    ));
+
+    match connect(
+        &connector,
+        &acceptor,
+        ConnectOptions::new(name("other.internal")).sni("front.example.com"),
+    ) {
+        Err(ConnectError::NameMismatch(n)) => assert_eq!(n, name("other.internal")),
+        other => panic!("expected a name mismatch, got {:?}", other),
+    }
+
+    match connect(
+        &connector,
+        &acceptor,
+        ConnectOptions::new(name("backend.internal")).sni("127.0.0.1"),
+    ) {
+        Err(ConnectError::InvalidServerName(e)) => assert_eq!(e.name(), "127.0.0.1"),
+        other => panic!("expected an invalid name, got {:?}", other),
+    }
+}
+
+#[cfg(feature = "x509")]
+#[test]
+fn wildcard_names_match_one_label() {
+    let ca = t!(CertificateAuthority::new("tls-async test root"));
+    let leaf = t!(ca.issue(&["*.example.com", "10.0.0.1"]));
+    let info = t!(tls_async::CertificateInfo::parse(&leaf.certificate()));
+    assert!(info.is_valid_for(&name("www.EXAMPLE.com")));
+    assert!(!info.is_valid_for(&name("example.com")));
+    assert!(!info.is_valid_for(&name("a.b.example.com")));
+    assert!(info.is_valid_for(&name("10.0.0.1")));
+    assert!(!info.is_valid_for(&name("10.0.0.2")));
+}
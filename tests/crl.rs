@@ -0,0 +1,221 @@
+#![cfg(all(feature = "pki", feature = "x509"))]
+
+use std::time::Duration;
+
+use futures::executor::block_on;
+use futures::future;
+use tls_async::testing::duplex;
+use tls_async::{
+    CertificateAuthority, ConnectError, IssuedCertificate, RevocationList, RevocationLists,
+    TlsAcceptor, TlsConnector,
+};
+
+macro_rules! t {
+    ($e:expr) => {
+        match $e {
+            Ok(e) => e,
+            Err(e) => panic!("{} failed with {:?}", stringify!($e), e),
+        }
+    };
+}
+
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+fn handshake(
+    ca: &CertificateAuthority,
+    leaf: &IssuedCertificate,
+    lists: &RevocationLists,
+) -> Result<(), ConnectError> {
+    let acceptor = t!(TlsAcceptor::new(leaf.identity()));
+    let connector = t!(TlsConnector::builder()
+        .add_root_certificate(ca.root_certificate())
+        .revocation_lists(lists.clone())
+        .build());
+
+    block_on(async {
+        let (client, server) = duplex(64 * 1024);
+        let (client, server) = future::join(
+            connector.connect_verified("localhost", client),
+            acceptor.accept(server),
+        )
+        .await;
+        t!(server);
+        client.map(drop)
+    })
+}
+
+#[test]
+fn revoked_certificate_is_refused() {
+    let ca = t!(CertificateAuthority::new("tls-async test root"));
+    let revoked = t!(ca.issue(&["localhost"]));
+    let good = t!(ca.issue(&["localhost"]));
+    let crl = t!(ca.revocation_list_pem(&[&revoked], DAY));
+    let lists = RevocationLists::new(t!(RevocationList::from_pem(
+        crl.as_bytes(),
+        &ca.root_certificate()
+    )));
+
+    match handshake(&ca, &revoked, &lists) {
+        Err(ConnectError::Revoked(e)) => assert_eq!(e.issuer(), "CN=tls-async test root"),
+        other => panic!("expected a revoked error, got {:?}", other),
+    }
+    t!(handshake(&ca, &good, &lists));
+}
+
+#[test]
+fn lists_from_other_issuers_are_ignored() {
+    let ca = t!(CertificateAuthority::new("tls-async test root"));
+    let other = t!(CertificateAuthority::new("some other root"));
+    let leaf = t!(ca.issue(&["localhost"]));
+    // Same serial, different issuer.
+    let other_leaf = t!(other.issue(&["localhost"]));
+    let crl = t!(other.revocation_list_pem(&[&other_leaf], DAY));
+    let lists = RevocationLists::new(t!(RevocationList::from_pem(
+        crl.as_bytes(),
+        &other.root_certificate()
+    )));
+
+    t!(handshake(&ca, &leaf, &lists));
+}
+
+#[test]
+fn lists_from_issuers_with_the_same_name_are_ignored() {
+    let ca = t!(CertificateAuthority::new("tls-async test root"));
+    let other = t!(CertificateAuthority::new("tls-async test root"));
+    let leaf = t!(ca.issue(&["localhost"]));
+    // Same serial and issuer name, different issuer key.
+    let other_leaf = t!(other.issue(&["localhost"]));
+    let crl = t!(other.revocation_list_pem(&[&other_leaf], DAY));
+    let lists = RevocationLists::new(t!(RevocationList::from_pem(
+        crl.as_bytes(),
+        &other.root_certificate()
+    )));
+
+    t!(handshake(&ca, &leaf, &lists));
+}
+
+#[test]
+fn lists_must_be_signed_by_the_given_issuer() {
+    let ca = t!(CertificateAuthority::new("tls-async test root"));
+    let impostor = t!(CertificateAuthority::new("tls-async test root"));
+    let other = t!(CertificateAuthority::new("some other root"));
+    let crl = t!(impostor.revocation_list_pem(&[], DAY));
+
+    t!(RevocationList::from_pem(
+        crl.as_bytes(),
+        &impostor.root_certificate()
+    ));
+    assert!(RevocationList::from_pem(crl.as_bytes(), &ca.root_certificate()).is_err());
+    assert!(RevocationList::from_pem(crl.as_bytes(), &other.root_certificate()).is_err());
+}
+
+#[test]
+fn stale_lists_are_refused() {
+    let ca = t!(CertificateAuthority::new("tls-async test root"));
+    let other = t!(CertificateAuthority::new("some other root"));
+    let leaf = t!(ca.issue(&["localhost"]));
+    let crl = t!(ca.revocation_list_pem(&[], Duration::from_secs(0)));
+    let lists = RevocationLists::new(t!(RevocationList::from_pem(
+        crl.as_bytes(),
+        &ca.root_certificate()
+    )));
+
+    std::thread::sleep(Duration::from_secs(1));
+    match handshake(&ca, &leaf, &lists) {
+        Err(ConnectError::RevocationUnknown(_)) => {}
+        other => panic!("expected an unknown revocation status, got {:?}", other),
+    }
+
+    // A current list from the same issuer is enough.
+    let crl = t!(ca.revocation_list_pem(&[], DAY));
+    let mut current = t!(RevocationList::from_pem(
+        crl.as_bytes(),
+        &ca.root_certificate()
+    ));
+    current.extend(t!(RevocationList::from_pem(
+        t!(ca.revocation_list_pem(&[], Duration::from_secs(0))).as_bytes(),
+        &ca.root_certificate()
+    )));
+    lists.replace(current);
+    t!(handshake(&ca, &leaf, &lists));
+
+    // Stale lists from other issuers do not matter.
+    let crl = t!(other.revocation_list_pem(&[], Duration::from_secs(0)));
+    lists.replace(t!(RevocationList::from_pem(
+        crl.as_bytes(),
+        &other.root_certificate()
+    )));
+    std::thread::sleep(Duration::from_secs(1));
+    t!(handshake(&ca, &leaf, &lists));
+}
+
+#[test]
+fn connect_applies_revocation_lists() {
+    let ca = t!(CertificateAuthority::new("tls-async test root"));
+    let leaf = t!(ca.issue(&["localhost"]));
+    let crl = t!(ca.revocation_list_pem(&[&leaf], DAY));
+    let lists = RevocationLists::new(t!(RevocationList::from_pem(
+        crl.as_bytes(),
+        &ca.root_certificate()
+    )));
+    let acceptor = t!(TlsAcceptor::new(leaf.identity()));
+    let connector = t!(TlsConnector::builder()
+        .add_root_certificate(ca.root_certificate())
+        .revocation_lists(lists)
+        .build());
+
+    let res = block_on(async {
+        let (client, server) = duplex(64 * 1024);
+        let (client, server) = future::join(
+            connector.connect("localhost", client),
+            acceptor.accept(server),
+        )
+        .await;
+        t!(server);
+        client.map(drop)
+    });
+    match res {
+        Err(ConnectError::Revoked(_)) => {}
+        other => panic!("expected a revoked error, got {:?}", other),
+    }
+}
+
+#[test]
+fn lists_can_be_replaced_at_runtime() {
+    let ca = t!(CertificateAuthority::new("tls-async test root"));
+    let leaf = t!(ca.issue(&["localhost"]));
+    let lists = RevocationLists::default();
+    t!(handshake(&ca, &leaf, &lists));
+
+    let crl = t!(ca.revocation_list_pem(&[&leaf], DAY));
+    lists.replace(t!(RevocationList::from_pem(
+        crl.as_bytes(),
+        &ca.root_certificate()
+    )));
+    match handshake(&ca, &leaf, &lists) {
+        Err(ConnectError::Revoked(_)) => {}
+        other => panic!("expected a revoked error, got {:?}", other),
+    }
+
+    lists.replace(Vec::new());
+    t!(handshake(&ca, &leaf, &lists));
+}
+
+#[test]
+fn parses_pem_lists() {
+    let ca = t!(CertificateAuthority::new("tls-async test root"));
+    let a = t!(ca.issue(&["a"]));
+    let b = t!(ca.issue(&["b"]));
+    let pem = t!(ca.revocation_list_pem(&[&a, &b], DAY)) + &t!(ca.revocation_list_pem(&[], DAY));
+
+    let lists = t!(RevocationList::from_pem(
+        pem.as_bytes(),
+        &ca.root_certificate()
+    ));
+    assert_eq!(lists.len(), 2);
+    assert_eq!(lists[0].len(), 2);
+    assert!(lists[1].is_empty());
+    assert_eq!(lists[0].issuer(), "CN=tls-async test root");
+    assert!(lists[0].next_update().is_some());
+    assert!(RevocationList::from_der(b"not a crl", &ca.root_certificate()).is_err());
+}
@@ -0,0 +1,103 @@
+#![cfg(feature = "pki")]
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use futures::executor::block_on;
+use tls_async::testing::connected_pair;
+use tls_async::{CertificateAuthority, EnvTrustError, TlsAcceptor, TlsConnector};
+
+macro_rules! t {
+    ($e:expr) => {
+        match $e {
+            Ok(e) => e,
+            Err(e) => panic!("{} failed with {:?}", stringify!($e), e),
+        }
+    };
+}
+
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("tls-async-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    t!(fs::create_dir_all(&dir));
+    dir
+}
+
+// The variables are process-wide, so every case runs in one test.
+#[test]
+fn env_trust_loads_and_reports() {
+    let ca = t!(CertificateAuthority::new("tls-async env root"));
+    let other = t!(CertificateAuthority::new("some other root"));
+    let leaf = t!(ca.issue(&["localhost"]));
+    let acceptor = t!(TlsAcceptor::new(leaf.identity()));
+
+    let root = scratch_dir("env-trust");
+    let file = root.join("bundle.pem");
+    t!(fs::write(&file, other.root_certificate_pem()));
+    let dir = root.join("certs");
+    t!(fs::create_dir(&dir));
+    t!(fs::write(dir.join("ca.pem"), ca.root_certificate_pem()));
+    t!(fs::write(dir.join("README"), "not a certificate\n"));
+
+    env::set_var("SSL_CERT_FILE", &file);
+    env::set_var("SSL_CERT_DIR", &dir);
+    let mut builder = TlsConnector::builder();
+    builder.disable_built_in_roots(true);
+    let report = t!(builder.with_env_trust());
+    assert_eq!(
+        report.loaded(),
+        &[(file.clone(), 1), (dir.join("ca.pem"), 1)][..]
+    );
+    assert_eq!(report.skipped().len(), 1);
+    assert_eq!(report.skipped()[0].0, dir.join("README"));
+
+    let connector = t!(builder.build());
+    block_on(async {
+        t!(connected_pair(&connector, &acceptor, "localhost").await);
+    });
+
+    // Unset variables load nothing.
+    env::remove_var("SSL_CERT_FILE");
+    env::set_var("SSL_CERT_DIR", "");
+    let report = t!(TlsConnector::builder().with_env_trust());
+    assert!(report.loaded().is_empty());
+    assert!(report.skipped().is_empty());
+
+    // A missing path is an error naming it.
+    let missing = root.join("missing.pem");
+    env::set_var("SSL_CERT_FILE", &missing);
+    match TlsConnector::builder().with_env_trust() {
+        Err(EnvTrustError::Read(path, _)) => assert_eq!(path, missing),
+        other => panic!("expected a read error, got {:?}", other),
+    }
+
+    // So is a file that holds no certificates.
+    let garbage = dir.join("README");
+    env::set_var("SSL_CERT_FILE", &garbage);
+    match TlsConnector::builder().with_env_trust() {
+        Err(EnvTrustError::Empty(path)) => assert_eq!(path, garbage),
+        other => panic!("expected an empty bundle error, got {:?}", other),
+    }
+
+    // A failure after some roots were read leaves the builder unchanged.
+    let ca_file = dir.join("ca.pem");
+    env::set_var("SSL_CERT_FILE", &ca_file);
+    env::set_var("SSL_CERT_DIR", &missing);
+    let mut builder = TlsConnector::builder();
+    builder.disable_built_in_roots(true);
+    match builder.with_env_trust() {
+        Err(EnvTrustError::Read(path, _)) => assert_eq!(path, missing),
+        other => panic!("expected a read error, got {:?}", other),
+    }
+    let connector = t!(builder.build());
+    block_on(async {
+        assert!(connected_pair(&connector, &acceptor, "localhost")
+            .await
+            .is_err());
+    });
+
+    env::remove_var("SSL_CERT_FILE");
+    env::remove_var("SSL_CERT_DIR");
+    let _ = fs::remove_dir_all(&root);
+}
@@ -4,7 +4,7 @@ use cfg_if::cfg_if;
 use futures::io::{AsyncReadExt, AsyncWriteExt};
 use futures::FutureExt;
 use futures_tokio_compat::Compat;
-use tls_async::{Error, TlsConnector};
+use tls_async::{ConnectError, Error, TlsConnector};
 use tokio::net::TcpStream;
 
 fn check_cause(err: Error, s: &str) {
@@ -16,6 +16,13 @@ fn check_cause(err: Error, s: &str) {
     );
 }
 
+fn tls_error(err: ConnectError) -> Error {
+    match err {
+        ConnectError::Tls(e) => e,
+        e => panic!("expected a TLS error, got {:?}", e),
+    }
+}
+
 macro_rules! t {
     ($e:expr) => {
         match $e {
@@ -110,5 +117,24 @@ fn wrong_hostname_error() {
     let res = rt.block_on(fut_result.fuse().boxed());
 
     assert!(res.is_err());
-    assert_bad_hostname_error(res.err().unwrap());
+    assert_bad_hostname_error(tls_error(res.err().unwrap()));
+}
+
+#[test]
+fn public_cert_fails_without_built_in_roots() {
+    drop(env_logger::try_init());
+
+    let fut_result = async {
+        let addr = t!("google.com:443".to_socket_addrs()).next().unwrap();
+        let socket = Compat::new(t!(TcpStream::connect(&addr).await));
+        let mut builder = TlsConnector::builder();
+        builder.disable_built_in_roots(true);
+        let connector = t!(builder.build());
+        connector.connect("google.com", socket).await
+    };
+
+    let rt = t!(tokio::runtime::Runtime::new());
+    let res = rt.block_on(fut_result.fuse().boxed());
+
+    assert!(res.is_err());
 }
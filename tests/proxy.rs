@@ -0,0 +1,255 @@
+use std::io;
+
+use futures::executor::block_on;
+use futures::io::{AsyncReadExt, AsyncWriteExt};
+use futures::{future, FutureExt, StreamExt};
+use futures_tokio_compat::Compat;
+use tls_async::proxy::tunnel;
+use tls_async::testing::duplex;
+use tls_async::{ConnectError, Credentials, Identity, Proxy, TlsAcceptor, TlsConnector};
+use tokio::net::{TcpListener, TcpStream};
+
+macro_rules! t {
+    ($e:expr) => {
+        match $e {
+            Ok(e) => e,
+            Err(e) => panic!("{} failed with {:?}", stringify!($e), e),
+        }
+    };
+}
+
+fn contexts() -> (TlsAcceptor, TlsConnector) {
+    let der = include_bytes!("../examples/identity.p12");
+    let identity = t!(Identity::from_pkcs12(der, "mypass"));
+    let acceptor = t!(TlsAcceptor::new(identity));
+
+    // The stand-in proxies below check the tunnel, not the certificate.
+    let connector = t!(TlsConnector::builder()
+        .danger_accept_invalid_certs(true)
+        .build());
+
+    (acceptor, connector)
+}
+
+/// Answers a single HTTP CONNECT request for `foobar.com:443` and then acts as
+/// the target server itself.
+async fn http_proxy(socket: Compat<TcpStream>, expected_auth: Option<&str>, acceptor: TlsAcceptor) {
+    let mut socket = socket;
+    let mut header = vec![];
+    while !header.ends_with(b"\r\n\r\n") {
+        let mut byte = [0u8];
+        t!(socket.read_exact(&mut byte).await);
+        header.push(byte[0]);
+    }
+    let header = String::from_utf8(header).unwrap();
+    assert!(header.starts_with("CONNECT foobar.com:443 HTTP/1.1\r\n"));
+
+    let auth = header
+        .lines()
+        .find(|line| line.starts_with("Proxy-Authorization: "))
+        .map(|line| &line["Proxy-Authorization: ".len()..]);
+    if auth != expected_auth {
+        t!(socket
+            .write_all(b"HTTP/1.1 407 Proxy Authentication Required\r\n\r\n")
+            .await);
+        return;
+    }
+    t!(socket
+        .write_all(b"HTTP/1.1 200 Connection established\r\n\r\n")
+        .await);
+
+    let mut stream = t!(acceptor.accept(socket).await);
+    t!(stream.write_all(b"through the tunnel").await);
+    t!(stream.close().await);
+}
+
+/// Answers a single SOCKS5 CONNECT request for `foobar.com:443` and then acts
+/// as the target server itself.
+async fn socks5_proxy(
+    socket: Compat<TcpStream>,
+    credentials: Option<(&str, &str)>,
+    acceptor: TlsAcceptor,
+) {
+    let mut socket = socket;
+    let mut greeting = [0u8; 2];
+    t!(socket.read_exact(&mut greeting).await);
+    assert_eq!(greeting[0], 5);
+    let mut methods = vec![0u8; greeting[1] as usize];
+    t!(socket.read_exact(&mut methods).await);
+
+    match credentials {
+        Some((username, password)) => {
+            assert!(methods.contains(&2));
+            t!(socket.write_all(&[5, 2]).await);
+
+            let mut version_len = [0u8; 2];
+            t!(socket.read_exact(&mut version_len).await);
+            let mut user = vec![0u8; version_len[1] as usize];
+            t!(socket.read_exact(&mut user).await);
+            let mut len = [0u8];
+            t!(socket.read_exact(&mut len).await);
+            let mut pass = vec![0u8; len[0] as usize];
+            t!(socket.read_exact(&mut pass).await);
+
+            if user != username.as_bytes() || pass != password.as_bytes() {
+                t!(socket.write_all(&[1, 1]).await);
+                return;
+            }
+            t!(socket.write_all(&[1, 0]).await);
+        }
+        None => t!(socket.write_all(&[5, 0]).await),
+    }
+
+    let mut request = [0u8; 5];
+    t!(socket.read_exact(&mut request).await);
+    assert_eq!(&request[..4], &[5, 1, 0, 3]);
+    let mut host = vec![0u8; request[4] as usize];
+    t!(socket.read_exact(&mut host).await);
+    assert_eq!(host, b"foobar.com");
+    let mut port = [0u8; 2];
+    t!(socket.read_exact(&mut port).await);
+    assert_eq!(u16::from_be_bytes(port), 443);
+    t!(socket.write_all(&[5, 0, 0, 1, 127, 0, 0, 1, 0, 0]).await);
+
+    let mut stream = t!(acceptor.accept(socket).await);
+    t!(stream.write_all(b"through the tunnel").await);
+    t!(stream.close().await);
+}
+
+fn run_client(
+    proxy: Proxy,
+    server: impl FnOnce(Compat<TcpStream>, TlsAcceptor) -> futures::future::BoxFuture<'static, ()>,
+) -> Result<Vec<u8>, ConnectError> {
+    drop(env_logger::try_init());
+
+    let rt = t!(tokio::runtime::Runtime::new());
+    let (srv, addr) = rt.block_on(
+        async {
+            let srv = t!(TcpListener::bind("127.0.0.1:0").await);
+            let addr = t!(srv.local_addr());
+            (srv, addr)
+        }
+        .boxed(),
+    );
+    let (acceptor, connector) = contexts();
+
+    let fut_server = async move {
+        let mut incoming = srv.incoming();
+        let socket = Compat::new(t!(incoming.next().await.unwrap()));
+        server(socket, acceptor).await;
+    };
+
+    let fut_client = async move {
+        let socket = Compat::new(t!(TcpStream::connect(&addr).await));
+        let mut stream = connector
+            .connect_via(&proxy, "foobar.com", 443, socket)
+            .await?;
+        let mut buf = vec![];
+        t!(stream.read_to_end(&mut buf).await);
+        Ok(buf)
+    };
+
+    let ((), res) = rt.block_on(futures::future::join(fut_server, fut_client).boxed());
+    res
+}
+
+#[test]
+fn http_connect_with_basic_auth() {
+    let proxy = Proxy::HttpConnect(Some(Credentials::new("user", "secret")));
+    let res = run_client(proxy, |socket, acceptor| {
+        http_proxy(socket, Some("Basic dXNlcjpzZWNyZXQ="), acceptor).boxed()
+    });
+    assert_eq!(t!(res), b"through the tunnel");
+}
+
+#[test]
+fn http_connect_rejected_credentials() {
+    let proxy = Proxy::HttpConnect(Some(Credentials::new("user", "wrong")));
+    let res = run_client(proxy, |socket, acceptor| {
+        http_proxy(socket, Some("Basic dXNlcjpzZWNyZXQ="), acceptor).boxed()
+    });
+    match res {
+        Err(ConnectError::Io(ref e)) if e.kind() == std::io::ErrorKind::PermissionDenied => {}
+        other => panic!("unexpected result {:?}", other),
+    }
+}
+
+#[test]
+fn socks5_without_auth() {
+    let res = run_client(Proxy::Socks5(None), |socket, acceptor| {
+        socks5_proxy(socket, None, acceptor).boxed()
+    });
+    assert_eq!(t!(res), b"through the tunnel");
+}
+
+#[test]
+fn socks5_with_password() {
+    let proxy = Proxy::Socks5(Some(Credentials::new("user", "secret")));
+    let res = run_client(proxy, |socket, acceptor| {
+        socks5_proxy(socket, Some(("user", "secret")), acceptor).boxed()
+    });
+    assert_eq!(t!(res), b"through the tunnel");
+}
+
+#[test]
+fn socks5_rejected_credentials() {
+    let proxy = Proxy::Socks5(Some(Credentials::new("user", "wrong")));
+    let res = run_client(proxy, |socket, acceptor| {
+        socks5_proxy(socket, Some(("user", "secret")), acceptor).boxed()
+    });
+    match res {
+        Err(ConnectError::Io(ref e)) if e.kind() == std::io::ErrorKind::PermissionDenied => {}
+        other => panic!("unexpected result {:?}", other),
+    }
+}
+
+#[test]
+fn connect_via_rejects_invalid_names_before_writing() {
+    let connector = t!(TlsConnector::new());
+    let (client, mut proxy) = duplex(1024);
+    let domain = "foobar.com:443 HTTP/1.1\r\nX-Injected: 1\r\n\r\nGET / HTTP/1.1";
+
+    let res = block_on(connector.connect_via(&Proxy::HttpConnect(None), domain, 443, client));
+    match res {
+        Err(ConnectError::InvalidServerName(ref e)) => assert_eq!(e.name(), domain),
+        other => panic!("unexpected result {:?}", other.map(|_| ())),
+    }
+
+    let mut sent = vec![];
+    t!(block_on(proxy.read_to_end(&mut sent)));
+    assert!(sent.is_empty());
+}
+
+#[test]
+fn tunnel_rejects_invalid_names_before_writing() {
+    for proxy in &[Proxy::HttpConnect(None), Proxy::Socks5(None)] {
+        let (mut client, mut server) = duplex(1024);
+        let err = block_on(tunnel(proxy, &mut client, "foobar.com\r\nX: 1", 443)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        drop(client);
+        let mut sent = vec![];
+        t!(block_on(server.read_to_end(&mut sent)));
+        assert!(sent.is_empty());
+    }
+}
+
+#[test]
+fn socks5_checks_auth_reply_version() {
+    let proxy = Proxy::Socks5(Some(Credentials::new("user", "secret")));
+    let (mut client, mut server) = duplex(1024);
+
+    let fut_server = async move {
+        let mut greeting = [0u8; 4];
+        t!(server.read_exact(&mut greeting).await);
+        t!(server.write_all(&[5, 2]).await);
+        let mut auth = [0u8; 1 + 1 + 4 + 1 + 6];
+        t!(server.read_exact(&mut auth).await);
+        // A success status under the wrong subnegotiation version.
+        t!(server.write_all(&[5, 0]).await);
+    };
+    let fut_client = tunnel(&proxy, &mut client, "foobar.com", 443);
+
+    let ((), res) = block_on(future::join(fut_server, fut_client));
+    assert_eq!(res.unwrap_err().kind(), io::ErrorKind::InvalidData);
+}
@@ -0,0 +1,213 @@
+#![cfg(feature = "pki")]
+
+use std::net::{IpAddr, SocketAddr};
+
+use futures::executor::block_on;
+use futures::future;
+use futures::io::{AsyncReadExt, AsyncWriteExt};
+use tls_async::testing::duplex;
+use tls_async::{
+    AcceptError, CertificateAuthority, ProxyHeader, ProxyPolicy, TlsAcceptor, TlsConnector,
+};
+
+macro_rules! t {
+    ($e:expr) => {
+        match $e {
+            Ok(e) => e,
+            Err(e) => panic!("{} failed with {:?}", stringify!($e), e),
+        }
+    };
+}
+
+fn addr(s: &str) -> SocketAddr {
+    t!(s.parse())
+}
+
+fn balancer_policy() -> ProxyPolicy {
+    let mut policy = ProxyPolicy::new();
+    policy.trust(t!("10.0.0.0".parse()), 8);
+    policy
+}
+
+/// Sends `prefix` and then a ClientHello from `peer`, returning the header
+/// the acceptor saw.
+fn accept(
+    policy: &ProxyPolicy,
+    peer: &str,
+    prefix: &[u8],
+) -> Result<Option<ProxyHeader>, AcceptError> {
+    let ca = t!(CertificateAuthority::new("tls-async test root"));
+    let leaf = t!(ca.issue(&["localhost"]));
+    let acceptor = t!(TlsAcceptor::new(leaf.identity()));
+    let connector = t!(TlsConnector::builder()
+        .add_root_certificate(ca.root_certificate())
+        .build());
+
+    let (mut client, server) = duplex(64 * 1024);
+    block_on(async {
+        t!(client.write_all(prefix).await);
+        let client = async {
+            let mut stream = connector.connect("localhost", client).await.ok()?;
+            stream.write_all(b"ping").await.ok()?;
+            stream.flush().await.ok()
+        };
+        let server = async {
+            let mut stream = acceptor.accept_proxied(policy, addr(peer), server).await?;
+            let mut buf = [0; 4];
+            t!(stream.read_exact(&mut buf).await);
+            assert_eq!(&buf, b"ping");
+            Ok(stream.proxy_header().cloned())
+        };
+        future::join(client, server).await.1
+    })
+}
+
+#[test]
+fn v1_header_from_trusted_peer() {
+    let header = t!(accept(
+        &balancer_policy(),
+        "10.1.2.3:40000",
+        b"PROXY TCP4 192.0.2.1 198.51.100.1 56324 443\r\n",
+    ))
+    .expect("header");
+    assert_eq!(header.source(), Some(addr("192.0.2.1:56324")));
+    assert_eq!(header.destination(), Some(addr("198.51.100.1:443")));
+    assert!(header.tlvs().is_empty());
+
+    let header = t!(accept(
+        &balancer_policy(),
+        "10.1.2.3:40000",
+        b"PROXY UNKNOWN ffff::1 ffff::2 1 2\r\n",
+    ))
+    .expect("header");
+    assert_eq!(header.source(), None);
+}
+
+#[test]
+fn v2_header_with_tlvs() {
+    let mut prefix = b"\r\n\r\n\0\r\nQUIT\n\x21\x21".to_vec();
+    let mut body = Vec::new();
+    body.extend_from_slice(
+        &"2001:db8::1"
+            .parse::<std::net::Ipv6Addr>()
+            .unwrap()
+            .octets(),
+    );
+    body.extend_from_slice(
+        &"2001:db8::2"
+            .parse::<std::net::Ipv6Addr>()
+            .unwrap()
+            .octets(),
+    );
+    body.extend_from_slice(&1234u16.to_be_bytes());
+    body.extend_from_slice(&443u16.to_be_bytes());
+    body.extend_from_slice(b"\x02\x00\x0bexample.com");
+    body.extend_from_slice(b"\x05\x00\x02id");
+    prefix.extend_from_slice(&(body.len() as u16).to_be_bytes());
+    prefix.extend_from_slice(&body);
+
+    let header = t!(accept(&balancer_policy(), "10.0.0.1:1", &prefix)).expect("header");
+    assert_eq!(header.source(), Some(addr("[2001:db8::1]:1234")));
+    assert_eq!(header.destination(), Some(addr("[2001:db8::2]:443")));
+    assert_eq!(header.tlvs().len(), 2);
+    assert_eq!(header.tlv(0x02), Some(&b"example.com"[..]));
+    assert_eq!(header.tlv(0x05), Some(&b"id"[..]));
+}
+
+#[test]
+fn v2_local_header_has_no_addresses() {
+    let header = t!(accept(
+        &balancer_policy(),
+        "10.0.0.1:1",
+        b"\r\n\r\n\0\r\nQUIT\n\x20\x00\x00\x00",
+    ))
+    .expect("header");
+    assert_eq!(header.source(), None);
+    assert_eq!(header.destination(), None);
+}
+
+#[test]
+fn v2_datagram_headers_have_no_addresses() {
+    // UDP over IPv4, then UDP over IPv6.
+    for (family, len) in &[(0x12u8, 12u16), (0x22, 36)] {
+        let mut prefix = b"\r\n\r\n\0\r\nQUIT\n\x21".to_vec();
+        prefix.push(*family);
+        prefix.extend_from_slice(&len.to_be_bytes());
+        prefix.extend(vec![1u8; usize::from(*len)]);
+
+        let header = t!(accept(&balancer_policy(), "10.0.0.1:1", &prefix)).expect("header");
+        assert_eq!(header.source(), None);
+        assert_eq!(header.destination(), None);
+    }
+
+    assert!(accept(
+        &balancer_policy(),
+        "10.0.0.1:1",
+        b"\r\n\r\n\0\r\nQUIT\n\x21\x13\x00\x0c\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01",
+    )
+    .is_err());
+}
+
+#[test]
+fn untrusted_peers_cannot_send_headers() {
+    match accept(
+        &balancer_policy(),
+        "192.0.2.7:1",
+        b"PROXY TCP4 192.0.2.1 198.51.100.1 56324 443\r\n",
+    ) {
+        Err(AcceptError::UntrustedProxy(ip)) => {
+            assert_eq!(ip, "192.0.2.7".parse::<IpAddr>().unwrap())
+        }
+        other => panic!("expected an untrusted proxy, got {:?}", other),
+    }
+
+    assert_eq!(t!(accept(&balancer_policy(), "192.0.2.7:1", b"")), None);
+}
+
+#[test]
+fn trusted_peers_must_send_headers_unless_optional() {
+    match accept(&balancer_policy(), "10.0.0.1:1", b"") {
+        Err(AcceptError::InvalidProxyHeader(_)) => {}
+        other => panic!("expected a missing header, got {:?}", other),
+    }
+
+    let mut policy = balancer_policy();
+    policy.header_optional(true);
+    assert_eq!(t!(accept(&policy, "10.0.0.1:1", b"")), None);
+}
+
+#[test]
+fn malformed_headers_are_rejected() {
+    for prefix in &[
+        &b"PROXY TCP4 192.0.2.1 198.51.100.1 56324\r\n"[..],
+        &b"PROXY TCP6 192.0.2.1 198.51.100.1 56324 443\r\n"[..],
+        &b"PROXY TCP4 192.0.2.1 198.51.100.1 +1 443\r\n"[..],
+        &b"\r\n\r\n\0\r\nQUIT\n\x11\x11\x00\x00"[..],
+        &b"\r\n\r\n\0\r\nQUIT\n\x21\x11\x00\x04\x01\x02\x03\x04"[..],
+    ] {
+        match accept(&balancer_policy(), "10.0.0.1:1", prefix) {
+            Err(AcceptError::InvalidProxyHeader(_)) => {}
+            other => panic!("expected an invalid header, got {:?}", other),
+        }
+    }
+}
+
+#[test]
+fn policy_matches_networks() {
+    let mut policy = ProxyPolicy::new();
+    policy
+        .trust(t!("10.0.0.0".parse()), 8)
+        .trust(t!("fd00::".parse()), 8)
+        .trust(t!("192.0.2.1".parse()), 32);
+    assert!(policy.is_trusted(t!("10.255.0.1".parse())));
+    assert!(policy.is_trusted(t!("::ffff:10.0.0.1".parse())));
+    assert!(policy.is_trusted(t!("fd12::1".parse())));
+    assert!(policy.is_trusted(t!("192.0.2.1".parse())));
+    assert!(!policy.is_trusted(t!("192.0.2.2".parse())));
+    assert!(!policy.is_trusted(t!("11.0.0.1".parse())));
+    assert!(!ProxyPolicy::new().is_trusted(t!("127.0.0.1".parse())));
+
+    let mut everyone = ProxyPolicy::new();
+    everyone.trust(t!("0.0.0.0".parse()), 0);
+    assert!(everyone.is_trusted(t!("203.0.113.9".parse())));
+}
@@ -0,0 +1,103 @@
+use std::net::IpAddr;
+
+use futures::executor::block_on;
+use futures::future;
+use futures::io::AsyncReadExt;
+use tls_async::testing::duplex;
+use tls_async::{ConnectError, ServerName, TlsConnector};
+
+macro_rules! t {
+    ($e:expr) => {
+        match $e {
+            Ok(e) => e,
+            Err(e) => panic!("{} failed with {:?}", stringify!($e), e),
+        }
+    };
+}
+
+#[test]
+fn parses_names() {
+    assert_eq!(
+        t!("example.com".parse::<ServerName>()),
+        ServerName::Dns("example.com".to_owned())
+    );
+    assert_eq!(
+        t!("example.com.".parse::<ServerName>()),
+        ServerName::Dns("example.com".to_owned())
+    );
+    assert_eq!(
+        t!("_srv.my-host".parse::<ServerName>()),
+        ServerName::Dns("_srv.my-host".to_owned())
+    );
+    assert_eq!(
+        t!("127.0.0.1".parse::<ServerName>()),
+        ServerName::Ip("127.0.0.1".parse::<IpAddr>().unwrap())
+    );
+    assert_eq!(
+        t!("[::1]".parse::<ServerName>()),
+        ServerName::Ip("::1".parse::<IpAddr>().unwrap())
+    );
+}
+
+#[test]
+fn rejects_invalid_names() {
+    for name in &[
+        "",
+        ".",
+        "a..b",
+        "-a.com",
+        "a-.com",
+        "exa mple.com",
+        "example.com/path",
+        "host:443",
+        "256.1.1.1",
+        "1.2.3",
+        &"a".repeat(64),
+    ] {
+        let err = name.parse::<ServerName>().unwrap_err();
+        assert_eq!(err.name(), *name);
+    }
+}
+
+/// Starts a handshake, returning its result and the first bytes the server
+/// side read, which hold the ClientHello if one was sent.
+fn client_hello(domain: &str) -> (Result<(), ConnectError>, Vec<u8>) {
+    let connector = t!(TlsConnector::builder().build());
+    let (client, mut server) = duplex(64 * 1024);
+    block_on(async {
+        let read = async move {
+            let mut buf = vec![0; 64 * 1024];
+            let n = t!(server.read(&mut buf).await);
+            buf.truncate(n);
+            buf
+        };
+        let (result, written) =
+            future::join(connector.connect_verified(domain, client), read).await;
+        (result.map(|_| ()), written)
+    })
+}
+
+#[test]
+fn invalid_name_sends_nothing() {
+    match client_hello("not a host") {
+        (Err(ConnectError::InvalidServerName(e)), written) => {
+            assert_eq!(e.name(), "not a host");
+            assert!(written.is_empty());
+        }
+        (other, _) => panic!("expected an invalid name, got {:?}", other),
+    }
+}
+
+#[test]
+fn ip_address_is_not_sent_as_sni() {
+    fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+        haystack.windows(needle.len()).any(|w| w == needle)
+    }
+
+    let (_, written) = client_hello("localhost");
+    assert!(contains(&written, b"localhost"));
+
+    let (_, written) = client_hello("127.0.0.1");
+    assert!(!written.is_empty());
+    assert!(!contains(&written, b"127.0.0.1"));
+}
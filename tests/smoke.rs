@@ -1,11 +1,20 @@
-use std::io::Write;
+use std::io::{self, Write};
+use std::pin::Pin;
 use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
 
 use cfg_if::cfg_if;
-use futures::io::{AsyncReadExt, AsyncWriteExt};
-use futures::{FutureExt, StreamExt};
+use futures::executor::block_on;
+use futures::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use futures::{future, FutureExt, StreamExt};
 use futures_tokio_compat::Compat;
-use tls_async::{Identity, TlsAcceptor, TlsConnector};
+use tls_async::testing::{connected_pair, duplex};
+use tls_async::{
+    CountingObserver, Identity, TlsAcceptor, TlsAcceptorBuilder, TlsConnector, TlsConnectorBuilder,
+    TlsPool,
+};
 use tokio::net::{TcpListener, TcpStream};
 
 macro_rules! t {
@@ -225,7 +234,7 @@ cfg_if! {
         use std::env;
 
 
-        fn contexts() -> (TlsAcceptor, TlsConnector) {
+        fn builders() -> (TlsAcceptorBuilder, TlsConnectorBuilder) {
             let keys = openssl_keys();
 
             let pkcs12 = t!(Identity::from_pkcs12(&keys.pkcs12_der, "foobar"));
@@ -236,7 +245,7 @@ cfg_if! {
             let mut client = TlsConnector::builder();
             t!(client.add_root_certificate(cert).build());
 
-            (t!(srv.build()).into(), t!(client.build()).into())
+            (srv, client)
         }
     } else if #[cfg(any(target_os = "macos", target_os = "ios"))] {
         extern crate security_framework;
@@ -244,7 +253,7 @@ cfg_if! {
         use std::env;
         use std::fs::File;
 
-        fn contexts() -> (TlsAcceptor, TlsConnector) {
+        fn builders() -> (TlsAcceptorBuilder, TlsConnectorBuilder) {
             let keys = openssl_keys();
 
             let pkcs12 = t!(Identity::from_pkcs12(&keys.pkcs12_der, "foobar"));
@@ -254,7 +263,7 @@ cfg_if! {
             let mut client = TlsConnector::builder();
             client.add_root_certificate(cert);
 
-            (t!(srv.build()).into(), t!(client.build()).into())
+            (srv, client)
         }
     } else {
         extern crate schannel;
@@ -280,7 +289,7 @@ cfg_if! {
 
         const FRIENDLY_NAME: &'static str = "tls-async localhost testing cert";
 
-        fn contexts() -> (TlsAcceptor, TlsConnector) {
+        fn builders() -> (TlsAcceptorBuilder, TlsConnectorBuilder) {
             let cert = localhost_cert();
             let mut store = t!(Memory::new()).into_store();
             t!(store.add_cert(&cert, CertAdd::Always));
@@ -289,7 +298,7 @@ cfg_if! {
 
             let srv = TlsAcceptor::builder(pkcs12);
             let client = TlsConnector::builder();
-            (t!(srv.build()).into(), t!(client.build()).into())
+            (srv, client)
         }
 
         // ====================================================================
@@ -496,6 +505,11 @@ test suite later.
     }
 }
 
+fn contexts() -> (TlsAcceptor, TlsConnector) {
+    let (srv, client) = builders();
+    (t!(srv.build()), t!(client.build()))
+}
+
 const AMT: usize = 128 * 1024;
 const EXPECTED: [u8; AMT] = [0u8; AMT];
 const SMALL_AMT: usize = 1024;
@@ -635,3 +649,460 @@ fn one_byte_at_a_time() {
 
     assert!(data == SMALL_EXPECTED.to_vec());
 }
+
+#[test]
+fn observer_sees_handshakes_and_traffic() {
+    drop(env_logger::try_init());
+
+    let rt = t!(tokio::runtime::Runtime::new());
+
+    let fut_bind = async move {
+        let srv = t!(TcpListener::bind("127.0.0.1:0").await);
+        let addr = t!(srv.local_addr());
+
+        (srv, addr)
+    };
+
+    let (srv, addr) = rt.block_on(fut_bind.boxed());
+
+    let server_observer = Arc::new(CountingObserver::new());
+    let client_observer = Arc::new(CountingObserver::new());
+    let (mut server_builder, mut client_builder) = builders();
+    let server_cx = t!(server_builder.observer(server_observer.clone()).build());
+    let client_cx = t!(client_builder.observer(client_observer.clone()).build());
+
+    let fut_server = async move {
+        let mut incoming = srv.incoming();
+        let socket = Compat::new(t!(incoming.next().await.unwrap()));
+        let mut stream = t!(server_cx.accept(socket).await);
+        let mut buf = vec![];
+        t!(stream.read_to_end(&mut buf).await);
+        buf
+    };
+
+    let fut_client = async move {
+        let socket = Compat::new(t!(TcpStream::connect(&addr).await));
+        let mut socket = t!(client_cx.connect("localhost", socket).await);
+        t!(socket.write_all(&SMALL_EXPECTED).await);
+        t!(socket.flush().await);
+        t!(socket.close().await);
+    };
+
+    let (data, ()) = rt.block_on(futures::future::join(fut_server, fut_client).boxed());
+
+    assert!(data == SMALL_EXPECTED.to_vec());
+
+    assert_eq!(client_observer.handshakes_started(), 1);
+    assert_eq!(client_observer.handshakes_succeeded(), 1);
+    assert_eq!(client_observer.bytes_written(), SMALL_AMT as u64);
+    assert_eq!(client_observer.closed(), 1);
+
+    assert_eq!(server_observer.handshakes_succeeded(), 1);
+    assert_eq!(server_observer.bytes_read(), SMALL_AMT as u64);
+    assert_eq!(server_observer.closed(), 1);
+}
+
+#[test]
+fn read_lines_without_extra_buffer() {
+    drop(env_logger::try_init());
+
+    let rt = t!(tokio::runtime::Runtime::new());
+
+    let fut_bind = async move {
+        let srv = t!(TcpListener::bind("127.0.0.1:0").await);
+        let addr = t!(srv.local_addr());
+
+        (srv, addr)
+    };
+
+    let (srv, addr) = rt.block_on(fut_bind.boxed());
+    let (server_cx, client_cx) = contexts();
+
+    let fut_server = async move {
+        let mut incoming = srv.incoming();
+        let socket = Compat::new(t!(incoming.next().await.unwrap()));
+        let mut stream = t!(server_cx.accept(socket).await);
+        t!(stream.write_all(b"first line\nsecond line\ntrailing").await);
+        t!(stream.flush().await);
+        t!(stream.close().await);
+    };
+
+    let fut_client = async move {
+        let socket = Compat::new(t!(TcpStream::connect(&addr).await));
+        let mut stream = t!(client_cx.connect("localhost", socket).await);
+        let mut lines = vec![];
+        loop {
+            let mut line = String::new();
+            if t!(stream.read_line(&mut line).await) == 0 {
+                break;
+            }
+            lines.push(line);
+        }
+        lines
+    };
+
+    rt.spawn(fut_server.boxed());
+    let lines = rt.block_on(fut_client.boxed());
+
+    assert_eq!(lines, vec!["first line\n", "second line\n", "trailing"]);
+}
+
+/// Counts the TLS records written to a transport and the largest record
+/// body seen, like the transport in `benches/vectored.rs`.
+struct Counting<S> {
+    inner: S,
+    records: usize,
+    largest: usize,
+    header: Vec<u8>,
+    remaining: usize,
+}
+
+impl<S> Counting<S> {
+    fn new(inner: S) -> Counting<S> {
+        Counting {
+            inner,
+            records: 0,
+            largest: 0,
+            header: Vec::with_capacity(5),
+            remaining: 0,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.records = 0;
+        self.largest = 0;
+    }
+
+    fn count_records(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            if self.remaining == 0 {
+                let take = std::cmp::min(5 - self.header.len(), data.len());
+                self.header.extend_from_slice(&data[..take]);
+                data = &data[take..];
+                if self.header.len() == 5 {
+                    self.records += 1;
+                    self.remaining = u16::from_be_bytes([self.header[3], self.header[4]]) as usize;
+                    self.largest = std::cmp::max(self.largest, self.remaining);
+                    self.header.clear();
+                }
+            } else {
+                let take = std::cmp::min(self.remaining, data.len());
+                self.remaining -= take;
+                data = &data[take..];
+            }
+        }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for Counting<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for Counting<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let r = Pin::new(&mut self.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(n)) = r {
+            self.count_records(&buf[..n]);
+        }
+        r
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_close(cx)
+    }
+}
+
+/// Sends `SMALL_EXPECTED` from server to client over a transport that moves
+/// one byte at a time, returning the number of records the server wrote for
+/// it, the largest record body and the data the client received.
+fn send_with_fragment(max_send_fragment: Option<usize>) -> (usize, usize, Vec<u8>) {
+    let (mut server_builder, client_builder) = builders();
+    if let Some(size) = max_send_fragment {
+        server_builder.max_send_fragment(size);
+    }
+    let server_cx = t!(server_builder.build());
+    let client_cx = t!(client_builder.build());
+    let (client, server) = duplex(1);
+
+    block_on(async {
+        let server = async {
+            let mut stream = t!(server_cx.accept(Counting::new(server)).await);
+            stream.get_mut().reset();
+            t!(stream.write_all(&SMALL_EXPECTED).await);
+            t!(stream.flush().await);
+            let counts = (stream.get_ref().records, stream.get_ref().largest);
+            t!(stream.close().await);
+            counts
+        };
+        let client = async {
+            let mut stream = t!(client_cx.connect("localhost", client).await);
+            let mut buf = vec![];
+            t!(stream.read_to_end(&mut buf).await);
+            buf
+        };
+        let ((records, largest), data) = future::join(server, client).await;
+        (records, largest, data)
+    })
+}
+
+#[test]
+fn max_send_fragment_splits_records() {
+    drop(env_logger::try_init());
+
+    let (records, largest, data) = send_with_fragment(None);
+    assert_eq!(records, 1);
+    assert!(largest > SMALL_AMT);
+    assert!(data == SMALL_EXPECTED.to_vec());
+
+    // Every record carries a single byte of plaintext, plus at most the
+    // cipher's nonce, tag and padding.
+    let (records, largest, data) = send_with_fragment(Some(1));
+    assert_eq!(records, SMALL_AMT);
+    assert!(largest < 64, "record body of {} bytes", largest);
+    assert!(data == SMALL_EXPECTED.to_vec());
+}
+
+#[test]
+fn pool_reuses_idle_connections() {
+    drop(env_logger::try_init());
+
+    let rt = t!(tokio::runtime::Runtime::new());
+
+    let fut_bind = async move {
+        let srv = t!(TcpListener::bind("127.0.0.1:0").await);
+        let addr = t!(srv.local_addr());
+
+        (srv, addr)
+    };
+
+    let (srv, addr) = rt.block_on(fut_bind.boxed());
+    let (server_cx, client_cx) = contexts();
+
+    let fut_server = async move {
+        let mut incoming = srv.incoming();
+        let socket = Compat::new(t!(incoming.next().await.unwrap()));
+        let mut stream = t!(server_cx.accept(socket).await);
+        for _ in 0..2 {
+            let mut buf = [0u8; 4];
+            t!(stream.read_exact(&mut buf).await);
+            assert_eq!(&buf, b"ping");
+            t!(stream.write_all(b"pong").await);
+            t!(stream.flush().await);
+        }
+        let mut rest = vec![];
+        t!(stream.read_to_end(&mut rest).await);
+    };
+
+    let connects = Arc::new(AtomicUsize::new(0));
+    let pool = {
+        let connects = connects.clone();
+        TlsPool::builder(client_cx).build(move |_: &str, _: u16| {
+            connects.fetch_add(1, Ordering::SeqCst);
+            async move { TcpStream::connect(&addr).await.map(Compat::new) }
+        })
+    };
+
+    let fut_client = async move {
+        for _ in 0..2 {
+            let mut conn = t!(pool.checkout("localhost", addr.port()).await);
+            t!(conn.write_all(b"ping").await);
+            t!(conn.flush().await);
+            let mut buf = [0u8; 4];
+            t!(conn.read_exact(&mut buf).await);
+            assert_eq!(&buf, b"pong");
+            pool.checkin(conn);
+            assert_eq!(pool.idle_count("localhost", addr.port()), 1);
+        }
+        assert_eq!(connects.load(Ordering::SeqCst), 1);
+
+        let conn = t!(pool.checkout("localhost", addr.port()).await);
+        assert_eq!(pool.idle_count("localhost", addr.port()), 0);
+        t!(conn.into_inner().close().await);
+    };
+
+    rt.block_on(futures::future::join(fut_server, fut_client).boxed());
+}
+
+#[test]
+fn pool_discards_connections_closed_by_peer() {
+    drop(env_logger::try_init());
+
+    let (server_cx, client_cx) = contexts();
+
+    let connects = Arc::new(AtomicUsize::new(0));
+    let (server_tx, mut server_rx) = futures::channel::mpsc::unbounded();
+    let pool = {
+        let connects = connects.clone();
+        TlsPool::builder(client_cx).build(move |_: &str, _: u16| {
+            connects.fetch_add(1, Ordering::SeqCst);
+            let (client, server) = duplex(16 * 1024);
+            server_tx.unbounded_send(server).unwrap();
+            future::ready(Ok(client))
+        })
+    };
+
+    block_on(async {
+        let accept = async { server_cx.accept(server_rx.next().await.unwrap()).await };
+        let (conn, server) = future::join(pool.checkout("localhost", 443), accept).await;
+        pool.checkin(t!(conn));
+        let mut server = t!(server);
+        t!(server.close().await);
+        drop(server);
+
+        let accept = async { server_cx.accept(server_rx.next().await.unwrap()).await };
+        let (conn, server) = future::join(pool.checkout("localhost", 443), accept).await;
+        t!(conn);
+        t!(server);
+    });
+
+    assert_eq!(connects.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn pool_discards_connections_with_unread_data() {
+    drop(env_logger::try_init());
+
+    let (server_cx, client_cx) = contexts();
+
+    let connects = Arc::new(AtomicUsize::new(0));
+    let (server_tx, mut server_rx) = futures::channel::mpsc::unbounded();
+    let pool = {
+        let connects = connects.clone();
+        TlsPool::builder(client_cx).build(move |_: &str, _: u16| {
+            connects.fetch_add(1, Ordering::SeqCst);
+            let (client, server) = duplex(16 * 1024);
+            server_tx.unbounded_send(server).unwrap();
+            future::ready(Ok(client))
+        })
+    };
+
+    block_on(async {
+        let accept = async { server_cx.accept(server_rx.next().await.unwrap()).await };
+        let (conn, server) = future::join(pool.checkout("localhost", 443), accept).await;
+        let conn = t!(conn);
+        let mut server = t!(server);
+        // A response the client never reads.
+        t!(server.write_all(b"stale").await);
+        t!(server.flush().await);
+        pool.checkin(conn);
+        assert_eq!(pool.idle_count("localhost", 443), 0);
+
+        let accept = async { server_cx.accept(server_rx.next().await.unwrap()).await };
+        let (conn, server) = future::join(pool.checkout("localhost", 443), accept).await;
+        let mut conn = t!(conn);
+        let mut server = t!(server);
+        t!(server.write_all(b"fresh").await);
+        t!(server.flush().await);
+        let mut buf = [0; 5];
+        t!(conn.read_exact(&mut buf).await);
+        assert_eq!(&buf, b"fresh");
+    });
+
+    assert_eq!(connects.load(Ordering::SeqCst), 2);
+}
+
+#[cfg(feature = "runtime")]
+#[test]
+fn connect_host_finds_listening_address() {
+    drop(env_logger::try_init());
+
+    let rt = t!(tokio::runtime::Runtime::new());
+
+    let fut_bind = async move {
+        let srv = t!(TcpListener::bind("127.0.0.1:0").await);
+        let addr = t!(srv.local_addr());
+
+        (srv, addr)
+    };
+
+    let (srv, addr) = rt.block_on(fut_bind.boxed());
+    let (server_cx, client_cx) = contexts();
+
+    let fut_server = async move {
+        let mut incoming = srv.incoming();
+        let socket = Compat::new(t!(incoming.next().await.unwrap()));
+        let mut stream = t!(server_cx.accept(socket).await);
+        t!(stream.write_all(&SMALL_EXPECTED).await);
+        t!(stream.flush().await);
+        t!(stream.close().await);
+    };
+    rt.spawn(fut_server.boxed());
+
+    // "localhost" may also resolve to ::1, where nothing is listening.
+    let data = futures::executor::block_on(async {
+        let mut stream = t!(client_cx.connect_host("localhost", addr.port()).await);
+        let mut buf = vec![];
+        t!(stream.read_to_end(&mut buf).await);
+        buf
+    });
+
+    assert!(data == SMALL_EXPECTED.to_vec());
+}
+
+#[cfg(feature = "runtime")]
+#[test]
+fn connect_host_reports_every_attempt() {
+    use tls_async::{ConnectError, ConnectHostError};
+
+    drop(env_logger::try_init());
+
+    // Grab a free port and close it again so the connection is refused.
+    let port = {
+        let listener = t!(std::net::TcpListener::bind("127.0.0.1:0"));
+        t!(listener.local_addr()).port()
+    };
+    let (_, client_cx) = contexts();
+
+    let res = futures::executor::block_on(client_cx.connect_host("127.0.0.1", port));
+    match res {
+        Err(ConnectHostError::Attempts(attempts)) => {
+            assert_eq!(attempts.len(), 1);
+            assert_eq!(attempts[0].0.port(), port);
+            match attempts[0].1 {
+                ConnectError::Io(_) => {}
+                ref e => panic!("unexpected error {:?}", e),
+            }
+        }
+        Err(e) => panic!("unexpected error {:?}", e),
+        Ok(_) => panic!("connected to a closed port"),
+    }
+}
+
+#[test]
+fn connected_pair_over_duplex() {
+    drop(env_logger::try_init());
+
+    let (server_cx, client_cx) = contexts();
+
+    let data = block_on(async {
+        let (mut client, mut server) =
+            t!(connected_pair(&client_cx, &server_cx, "localhost").await);
+
+        let write = async {
+            t!(server.write_all(&EXPECTED).await);
+            t!(server.close().await);
+        };
+        let read = async {
+            let mut buf = vec![];
+            t!(client.read_to_end(&mut buf).await);
+            buf
+        };
+        future::join(write, read).await.1
+    });
+
+    assert!(data == EXPECTED.to_vec());
+}
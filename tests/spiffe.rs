@@ -0,0 +1,204 @@
+#![cfg(all(feature = "pki", feature = "x509"))]
+
+use futures::executor::block_on;
+use futures::future;
+use tls_async::spiffe::SpiffeMatch;
+use tls_async::testing::duplex;
+use tls_async::{
+    CertificateAuthority, ConnectError, ConnectOptions, IssuedCertificate, ServerName, TlsAcceptor,
+    TlsConnector,
+};
+
+macro_rules! t {
+    ($e:expr) => {
+        match $e {
+            Ok(e) => e,
+            Err(e) => panic!("{} failed with {:?}", stringify!($e), e),
+        }
+    };
+}
+
+fn connect(
+    bundle: &CertificateAuthority,
+    svid: &IssuedCertificate,
+    expected: SpiffeMatch,
+) -> Result<Option<String>, ConnectError> {
+    let acceptor = t!(TlsAcceptor::new(svid.identity()));
+    let connector = t!(TlsConnector::builder()
+        .spiffe(&[bundle.root_certificate()], expected)
+        .build());
+
+    block_on(async {
+        let (client, server) = duplex(64 * 1024);
+        let (client, _server) = future::join(
+            connector.connect_verified("backend", client),
+            acceptor.accept(server),
+        )
+        .await;
+        client.map(|stream| stream.spiffe_id().map(str::to_owned))
+    })
+}
+
+#[test]
+fn matches_expected_id() {
+    let ca = t!(CertificateAuthority::new("example.org bundle"));
+    let svid = t!(ca.issue(&["spiffe://example.org/web"]));
+
+    let id = t!(connect(
+        &ca,
+        &svid,
+        SpiffeMatch::Id("spiffe://example.org/web".to_owned()),
+    ));
+    assert_eq!(id.as_deref(), Some("spiffe://example.org/web"));
+}
+
+#[test]
+fn matches_trust_domain() {
+    let ca = t!(CertificateAuthority::new("example.org bundle"));
+    let svid = t!(ca.issue(&["spiffe://example.org/ns/prod/sa/api"]));
+
+    let id = t!(connect(
+        &ca,
+        &svid,
+        SpiffeMatch::TrustDomain("example.org".to_owned()),
+    ));
+    assert_eq!(id.as_deref(), Some("spiffe://example.org/ns/prod/sa/api"));
+}
+
+#[test]
+fn rejects_other_id() {
+    let ca = t!(CertificateAuthority::new("example.org bundle"));
+    let svid = t!(ca.issue(&["spiffe://example.org/db"]));
+
+    match connect(
+        &ca,
+        &svid,
+        SpiffeMatch::Id("spiffe://example.org/web".to_owned()),
+    ) {
+        Err(ConnectError::SpiffeMismatch(e)) => {
+            assert_eq!(e.found(), Some("spiffe://example.org/db"))
+        }
+        other => panic!("expected a SPIFFE mismatch, got {:?}", other),
+    }
+}
+
+#[test]
+fn rejects_other_trust_domain() {
+    let ca = t!(CertificateAuthority::new("example.org bundle"));
+    let svid = t!(ca.issue(&["spiffe://example.com/web"]));
+
+    match connect(
+        &ca,
+        &svid,
+        SpiffeMatch::TrustDomain("example.org".to_owned()),
+    ) {
+        Err(ConnectError::SpiffeMismatch(_)) => {}
+        other => panic!("expected a SPIFFE mismatch, got {:?}", other),
+    }
+}
+
+#[test]
+fn rejects_certificate_without_id() {
+    let ca = t!(CertificateAuthority::new("example.org bundle"));
+    let cert = t!(ca.issue(&["backend"]));
+
+    match connect(
+        &ca,
+        &cert,
+        SpiffeMatch::TrustDomain("example.org".to_owned()),
+    ) {
+        Err(ConnectError::SpiffeMismatch(e)) => assert_eq!(e.found(), None),
+        other => panic!("expected a SPIFFE mismatch, got {:?}", other),
+    }
+}
+
+#[test]
+fn rejects_svid_from_other_bundle() {
+    let ca = t!(CertificateAuthority::new("example.org bundle"));
+    let other = t!(CertificateAuthority::new("attacker bundle"));
+    let svid = t!(other.issue(&["spiffe://example.org/web"]));
+
+    match connect(
+        &ca,
+        &svid,
+        SpiffeMatch::Id("spiffe://example.org/web".to_owned()),
+    ) {
+        Err(ConnectError::Tls(_)) => {}
+        other => panic!("expected a handshake failure, got {:?}", other),
+    }
+}
+
+#[test]
+fn every_connect_method_checks_the_id() {
+    let ca = t!(CertificateAuthority::new("example.org bundle"));
+    let svid = t!(ca.issue(&["spiffe://example.org/db"]));
+    let acceptor = t!(TlsAcceptor::new(svid.identity()));
+    let connector = t!(TlsConnector::builder()
+        .spiffe(
+            &[ca.root_certificate()],
+            SpiffeMatch::Id("spiffe://example.org/web".to_owned()),
+        )
+        .build());
+    let name: ServerName = t!("backend".parse());
+    let mut options = ConnectOptions::new(name.clone());
+    options.alpn(&["h2"]);
+
+    for method in 0..4 {
+        let res = block_on(async {
+            let (client, server) = duplex(64 * 1024);
+            let client = async {
+                match method {
+                    0 => connector.connect("backend", client).await,
+                    1 => connector.connect_verified("backend", client).await,
+                    2 => connector.connect_to(&name, client).await,
+                    _ => connector.connect_with(&options, client).await,
+                }
+            };
+            let (client, _server) = future::join(client, acceptor.accept(server)).await;
+            client.map(drop)
+        });
+        match res {
+            Err(ConnectError::SpiffeMismatch(_)) => {}
+            other => panic!(
+                "method {}: expected a SPIFFE mismatch, got {:?}",
+                method, other
+            ),
+        }
+    }
+}
+
+#[test]
+fn only_the_bundle_is_trusted() {
+    let ca = t!(CertificateAuthority::new("example.org bundle"));
+    let other = t!(CertificateAuthority::new("example.com bundle"));
+    // A root of another trust domain issuing an ID in this one.
+    let svid = t!(other.issue(&["spiffe://example.org/web"]));
+    let acceptor = t!(TlsAcceptor::new(svid.identity()));
+    let expected = SpiffeMatch::TrustDomain("example.org".to_owned());
+
+    let before = t!(TlsConnector::builder()
+        .add_root_certificate(other.root_certificate())
+        .spiffe(&[ca.root_certificate()], expected.clone())
+        .build());
+    let after = t!(TlsConnector::builder()
+        .spiffe(&[ca.root_certificate()], expected)
+        .add_root_certificate(other.root_certificate())
+        .disable_built_in_roots(false)
+        .build());
+
+    for connector in &[before, after] {
+        let res = block_on(async {
+            let (client, server) = duplex(64 * 1024);
+            let (client, _server) = future::join(
+                connector.connect("backend", client),
+                acceptor.accept(server),
+            )
+            .await;
+            client.map(drop)
+        });
+        match res {
+            Err(ConnectError::Tls(_)) => {}
+            other => panic!("expected a handshake failure, got {:?}", other),
+        }
+    }
+}
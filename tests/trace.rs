@@ -0,0 +1,143 @@
+#![cfg(all(feature = "tracing", feature = "pki"))]
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use futures::executor::block_on;
+use tls_async::testing::connected_pair;
+use tls_async::{CertificateAuthority, TlsAcceptor, TlsConnector};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata, Subscriber};
+
+macro_rules! t {
+    ($e:expr) => {
+        match $e {
+            Ok(e) => e,
+            Err(e) => panic!("{} failed with {:?}", stringify!($e), e),
+        }
+    };
+}
+
+/// Records span names with their fields, and events with the span they were
+/// emitted in.
+#[derive(Clone, Default)]
+struct Recorder {
+    spans: Arc<Mutex<Vec<(String, String)>>>,
+    stack: Arc<Mutex<Vec<u64>>>,
+    events: Arc<Mutex<Vec<(String, String)>>>,
+}
+
+#[derive(Default)]
+struct Fields {
+    message: String,
+    others: Vec<String>,
+}
+
+impl Visit for Fields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.others.push(format!("{}={}", field.name(), value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            self.message = format!("{:?}", value);
+        } else {
+            self.others.push(format!("{}={:?}", field.name(), value));
+        }
+    }
+}
+
+impl Recorder {
+    fn has_span(&self, name: &str, fields: &str) -> bool {
+        let spans = self.spans.lock().unwrap();
+        spans.iter().any(|(n, f)| n == name && f == fields)
+    }
+
+    fn has_event(&self, span: &str, message: &str) -> bool {
+        let events = self.events.lock().unwrap();
+        events.iter().any(|(s, m)| s == span && m == message)
+    }
+}
+
+impl Subscriber for Recorder {
+    fn enabled(&self, _: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, span: &Attributes<'_>) -> Id {
+        let mut fields = Fields::default();
+        span.record(&mut fields);
+        let mut spans = self.spans.lock().unwrap();
+        spans.push((span.metadata().name().to_owned(), fields.others.join(" ")));
+        Id::from_u64(spans.len() as u64)
+    }
+
+    fn record(&self, _: &Id, _: &Record<'_>) {}
+
+    fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+        let span = match self.stack.lock().unwrap().last() {
+            Some(&id) => self.spans.lock().unwrap()[id as usize - 1].0.clone(),
+            None => String::new(),
+        };
+        self.events.lock().unwrap().push((span, fields.message));
+    }
+
+    fn enter(&self, span: &Id) {
+        self.stack.lock().unwrap().push(span.into_u64());
+    }
+
+    fn exit(&self, _: &Id) {
+        self.stack.lock().unwrap().pop();
+    }
+}
+
+#[test]
+fn handshakes_are_traced() {
+    let ca = t!(CertificateAuthority::new("tls-async test root"));
+    let leaf = t!(ca.issue(&["localhost"]));
+    let acceptor = t!(TlsAcceptor::new(leaf.identity()));
+    let connector = t!(TlsConnector::builder()
+        .add_root_certificate(ca.root_certificate())
+        .build());
+
+    let recorder = Recorder::default();
+    tracing::subscriber::with_default(recorder.clone(), || {
+        block_on(async {
+            t!(connected_pair(&connector, &acceptor, "localhost").await);
+        })
+    });
+
+    assert!(recorder.has_span("tls_connect", "domain=localhost"));
+    assert!(recorder.has_span("tls_accept", ""));
+    assert!(recorder.has_event("tls_connect", "handshake would block"));
+    assert!(recorder.has_event("tls_connect", "handshake complete"));
+    assert!(recorder.has_event("tls_accept", "handshake complete"));
+}
+
+#[test]
+fn failed_handshakes_are_traced() {
+    let ca = t!(CertificateAuthority::new("tls-async test root"));
+    let other = t!(CertificateAuthority::new("some other root"));
+    let leaf = t!(other.issue(&["localhost"]));
+    let acceptor = t!(TlsAcceptor::new(leaf.identity()));
+    let connector = t!(TlsConnector::builder()
+        .add_root_certificate(ca.root_certificate())
+        .build());
+
+    let recorder = Recorder::default();
+    tracing::subscriber::with_default(recorder.clone(), || {
+        block_on(async {
+            assert!(connected_pair(&connector, &acceptor, "localhost")
+                .await
+                .is_err());
+        })
+    });
+
+    assert!(recorder.has_span("tls_connect", "domain=localhost"));
+    assert!(recorder.has_event("tls_connect", "handshake failed"));
+}
@@ -0,0 +1,181 @@
+#![cfg(all(feature = "pki", feature = "x509"))]
+
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use futures::executor::block_on;
+use tls_async::testing::connected_pair;
+use tls_async::x509::{ExtendedKeyUsage, KeyType, SubjectAltName};
+use tls_async::{
+    check_certificate_expiry, CertificateAuthority, CertificateInfo, CountingObserver, ServerName,
+    TlsAcceptor, TlsConnector,
+};
+
+macro_rules! t {
+    ($e:expr) => {
+        match $e {
+            Ok(e) => e,
+            Err(e) => panic!("{} failed with {:?}", stringify!($e), e),
+        }
+    };
+}
+
+#[test]
+fn parses_issued_certificate() {
+    let ca = t!(CertificateAuthority::new("tls-async test root"));
+    let leaf = t!(ca.issue_valid_for(
+        &["localhost", "127.0.0.1", "spiffe://example.org/web"],
+        Duration::from_secs(24 * 60 * 60),
+    ));
+    let info = t!(CertificateInfo::parse(&leaf.certificate()));
+
+    assert_eq!(info.subject(), "CN=localhost");
+    assert_eq!(info.subject_common_name(), Some("localhost"));
+    assert_eq!(info.issuer(), "CN=tls-async test root");
+    assert_eq!(
+        info.subject_alt_names(),
+        &[
+            SubjectAltName::Dns("localhost".to_owned()),
+            SubjectAltName::Ip("127.0.0.1".parse::<IpAddr>().unwrap()),
+            SubjectAltName::Uri("spiffe://example.org/web".to_owned()),
+        ][..]
+    );
+    assert_eq!(
+        info.extended_key_usages(),
+        &[ExtendedKeyUsage::ServerAuth, ExtendedKeyUsage::ClientAuth][..]
+    );
+    assert_eq!(info.key_type(), KeyType::Ec);
+    assert_eq!(info.key_bits(), 256);
+    assert!(!info.serial().is_empty());
+
+    let now = SystemTime::now();
+    assert!(info.is_valid_at(now));
+    assert!(!info.is_valid_at(now + Duration::from_secs(2 * 24 * 60 * 60)));
+    assert_eq!(info.fingerprint_sha256_hex().len(), 32 * 3 - 1);
+}
+
+#[test]
+fn parses_root_certificate() {
+    let ca = t!(CertificateAuthority::new("tls-async test root"));
+    let info = t!(CertificateInfo::parse(&ca.root_certificate()));
+    assert_eq!(info.subject(), info.issuer());
+    assert!(info.subject_alt_names().is_empty());
+    assert!(info.extended_key_usages().is_empty());
+}
+
+#[test]
+fn peer_certificate_matches_issued() {
+    let ca = t!(CertificateAuthority::new("tls-async test root"));
+    let leaf = t!(ca.issue(&["localhost"]));
+    let acceptor = t!(TlsAcceptor::new(leaf.identity()));
+    let connector = t!(TlsConnector::builder()
+        .add_root_certificate(ca.root_certificate())
+        .build());
+
+    block_on(async {
+        let (client, server) = t!(connected_pair(&connector, &acceptor, "localhost").await);
+        let peer = t!(client.peer_certificate()).expect("server sent a certificate");
+        let peer = t!(CertificateInfo::parse(&peer));
+        let local = t!(CertificateInfo::parse(&leaf.certificate()));
+        assert_eq!(peer.fingerprint_sha256(), local.fingerprint_sha256());
+        assert_eq!(peer.serial(), local.serial());
+
+        assert!(t!(server.peer_certificate()).is_none());
+    });
+}
+
+#[test]
+fn checks_names_against_subject_alt_names() {
+    let ca = t!(CertificateAuthority::new("tls-async test root"));
+    let leaf = t!(ca.issue(&["*.a.com", "B.example.", "10.0.0.1", "::1"]));
+    let info = t!(CertificateInfo::parse(&leaf.certificate()));
+    let valid_for = |name: &str| info.is_valid_for(&t!(name.parse::<ServerName>()));
+
+    assert!(valid_for("x.a.com"));
+    assert!(valid_for("X.A.COM"));
+    assert!(!valid_for("a.com"));
+    assert!(!valid_for("x.y.a.com"));
+    assert!(!valid_for("xa.com"));
+    assert!(!valid_for("x.a.com.evil"));
+
+    assert!(valid_for("b.example"));
+    assert!(valid_for("b.example."));
+    assert!(!valid_for("c.b.example"));
+    assert!(!valid_for("example"));
+
+    assert!(valid_for("10.0.0.1"));
+    assert!(valid_for("[::1]"));
+    assert!(!valid_for("10.0.0.2"));
+    assert!(!valid_for("::2"));
+
+    // DNS names only match DNS entries, and addresses only IP entries.
+    let leaf = t!(ca.issue(&["10.0.0.1"]));
+    let info = t!(CertificateInfo::parse(&leaf.certificate()));
+    assert!(!info.is_valid_for(&ServerName::Dns("10.0.0.1".to_owned())));
+    let leaf = t!(ca.issue(&["localhost"]));
+    let info = t!(CertificateInfo::parse(&leaf.certificate()));
+    assert!(!info.is_valid_for(&t!("127.0.0.1".parse())));
+    assert!(info.is_valid_for(&t!("localhost".parse())));
+}
+
+#[test]
+fn rejects_garbage() {
+    assert!(CertificateInfo::from_der(b"not a certificate").is_err());
+}
+
+#[test]
+fn acceptor_reports_its_certificate() {
+    let ca = t!(CertificateAuthority::new("tls-async test root"));
+    let leaf = t!(ca.issue(&["localhost"]));
+    let observer = Arc::new(CountingObserver::new());
+    let acceptor = t!(TlsAcceptor::builder(leaf.identity())
+        .observer(observer.clone())
+        .build());
+
+    let cert = t!(block_on(acceptor.certificate()));
+    assert_eq!(t!(cert.to_der()), t!(leaf.certificate().to_der()));
+    assert_eq!(observer.handshakes_started(), 0);
+}
+
+#[test]
+fn acceptor_warns_when_certificate_expires_soon() {
+    let ca = t!(CertificateAuthority::new("tls-async test root"));
+    let leaf = t!(ca.issue_valid_for(&["localhost"], Duration::from_secs(24 * 60 * 60)));
+    let observer = Arc::new(CountingObserver::new());
+    let acceptor = t!(TlsAcceptor::builder(leaf.identity())
+        .observer(observer.clone())
+        .build());
+
+    let expiry = t!(block_on(
+        acceptor.check_expiry(Duration::from_secs(60 * 60))
+    ));
+    assert!(!expiry.is_expiring());
+    assert!(expiry.remaining().unwrap() > Duration::from_secs(23 * 60 * 60));
+    assert_eq!(observer.certificates_expiring(), 0);
+
+    let expiry = t!(block_on(
+        acceptor.check_expiry(Duration::from_secs(30 * 24 * 60 * 60))
+    ));
+    assert!(expiry.is_expiring());
+    assert!(!expiry.is_expired());
+    assert_eq!(expiry.certificate().subject(), "CN=localhost");
+    assert_eq!(observer.certificates_expiring(), 1);
+}
+
+#[test]
+fn checks_client_certificate() {
+    let ca = t!(CertificateAuthority::new("tls-async test root"));
+    let client = t!(ca.issue_valid_for(&["client"], Duration::from_secs(0)));
+    let observer = CountingObserver::new();
+
+    std::thread::sleep(Duration::from_secs(1));
+    let expiry = t!(check_certificate_expiry(
+        &client.certificate(),
+        Duration::from_secs(0),
+        &observer
+    ));
+    assert!(expiry.is_expired());
+    assert!(expiry.is_expiring());
+    assert_eq!(observer.certificates_expiring(), 1);
+}